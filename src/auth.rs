@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What an API key gives access to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Searches, Torznab, RSS feeds, categories and torrent downloads
+    Search,
+    /// `/admin/*`, watch rules and sending torrents to download clients
+    Admin,
+    /// The `/stream` live feed
+    Stream,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Search => "search",
+            Scope::Admin => "admin",
+            Scope::Stream => "stream",
+        }
+    }
+}
+
+/// A named key, `name` only shows up in the logs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<Scope>,
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::Search]
+}
+
+/// Scope needed for a route pattern (e.g. `/torrent/{id}/send`), `None` when it stays public.
+/// Unknown routes need `search`, they end up as 404s.
+pub fn required_scope(pattern: Option<&str>) -> Option<Scope> {
+    match pattern {
+        // The homepage and the container health check
+        Some("/" | "/health") => None,
+        Some("/stream") => Some(Scope::Stream),
+        Some("/watch" | "/watch/{id}" | "/torrent/{id}/send") => Some(Scope::Admin),
+        Some(pattern) if pattern.starts_with("/admin/") => Some(Scope::Admin),
+        _ => Some(Scope::Search),
+    }
+}
+
+/// Key matching `presented`. Every key is compared through its SHA-256 digest, so the
+/// timing shows neither the length of the keys nor which one matched.
+pub fn find_key<'a>(keys: &'a [ApiKey], presented: &str) -> Option<&'a ApiKey> {
+    let presented = Sha256::digest(presented.as_bytes());
+    let mut found = None;
+    for key in keys {
+        let diff = Sha256::digest(key.key.as_bytes())
+            .iter()
+            .zip(presented.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b));
+        if diff == 0 && found.is_none() {
+            found = Some(key);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_and_keys() {
+        assert_eq!(required_scope(Some("/health")), None);
+        assert_eq!(required_scope(Some("/")), None);
+        assert_eq!(required_scope(Some("/api")), Some(Scope::Search));
+        assert_eq!(required_scope(Some("/torrent/{id}")), Some(Scope::Search));
+        assert_eq!(
+            required_scope(Some("/torrent/{id}/send")),
+            Some(Scope::Admin)
+        );
+        assert_eq!(
+            required_scope(Some("/admin/tmdb-cache")),
+            Some(Scope::Admin)
+        );
+        assert_eq!(required_scope(Some("/watch/{id}")), Some(Scope::Admin));
+        assert_eq!(required_scope(Some("/stream")), Some(Scope::Stream));
+        assert_eq!(required_scope(None), Some(Scope::Search));
+
+        let keys: Vec<ApiKey> = serde_json::from_str(
+            r#"[{"name": "sonarr", "key": "s3cr3t"}, {"name": "me", "key": "adm1n", "scopes": ["search", "admin"]}]"#,
+        )
+        .unwrap();
+        assert_eq!(keys[0].scopes, vec![Scope::Search]);
+        assert_eq!(
+            find_key(&keys, "adm1n").map(|k| k.name.as_str()),
+            Some("me")
+        );
+        assert!(find_key(&keys, "s3cr3").is_none());
+        assert!(find_key(&keys, "").is_none());
+    }
+}
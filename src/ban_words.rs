@@ -0,0 +1,169 @@
+use crate::search::tokenize;
+use qstring::QString;
+use regex::{Regex, RegexBuilder};
+use std::sync::OnceLock;
+
+static GLOBAL_BAN_WORDS: OnceLock<BanWords> = OnceLock::new();
+
+/// The `ban_words` of the configuration, applied to every search.
+pub fn global_ban_words() -> Option<&'static BanWords> {
+    GLOBAL_BAN_WORDS.get()
+}
+
+/// Compile the configured ban list, fails on an invalid regex.
+pub fn init_ban_words(entries: &[String]) -> Result<(), String> {
+    let ban_words = BanWords::parse(entries)?;
+    if ban_words.is_empty() {
+        return Ok(());
+    }
+    info!("{} global ban words loaded", ban_words.len());
+    if GLOBAL_BAN_WORDS.set(ban_words).is_err() {
+        warn!("Global ban words already initialized");
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+enum BanWord {
+    /// Consecutive name tokens, so `vfq` does not match `vfqx` and `web-dl` matches `WEB.DL`
+    Words(Vec<String>),
+    /// `re:` entry, case-insensitive
+    Regex(Regex),
+}
+
+/// A list of ban words: a torrent is dropped when its name contains any of them as whole
+/// words, or matches any of the `re:` regexes.
+#[derive(Debug, Clone, Default)]
+pub struct BanWords(Vec<BanWord>);
+
+impl BanWords {
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        let mut words = Vec::new();
+        for entry in entries.iter().map(|e| e.trim()) {
+            if let Some(pattern) = entry.strip_prefix("re:") {
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("Invalid ban word regex {}: {}", pattern, e))?;
+                words.push(BanWord::Regex(regex));
+            } else {
+                let tokens = tokenize(entry);
+                if !tokens.is_empty() {
+                    words.push(BanWord::Words(tokens));
+                }
+            }
+        }
+        Ok(BanWords(words))
+    }
+
+    /// Parse every `ban_words` query parameter, each a comma-separated list.
+    /// `None` when there is none or they are all blank.
+    pub fn from_query(qs: &QString) -> Result<Option<Self>, String> {
+        let entries: Vec<String> = qs
+            .to_pairs()
+            .into_iter()
+            .filter(|(key, _)| *key == "ban_words")
+            .flat_map(|(_, value)| split_param(value))
+            .collect();
+        let ban_words = Self::parse(&entries)?;
+        Ok((!ban_words.is_empty()).then_some(ban_words))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_banned(&self, name: &str) -> bool {
+        if self.0.is_empty() {
+            return false;
+        }
+        let tokens = tokenize(name);
+        self.0.iter().any(|word| match word {
+            BanWord::Words(words) => tokens.windows(words.len()).any(|w| w == words.as_slice()),
+            BanWord::Regex(regex) => regex.is_match(name),
+        })
+    }
+}
+
+/// Split a `ban_words` value on commas. Inside a `re:` entry, commas within brackets
+/// (`\d{1,2}`, `[a,b]`) or escaped with a backslash belong to the regex.
+fn split_param(value: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for c in value.chars() {
+        let in_regex = current.trim_start().starts_with("re:");
+        match c {
+            ',' if !escaped && (!in_regex || depth == 0) => {
+                entries.push(std::mem::take(&mut current));
+                depth = 0;
+                continue;
+            }
+            _ if escaped => escaped = false,
+            '\\' if in_regex => escaped = true,
+            '{' | '[' | '(' if in_regex => depth += 1,
+            '}' | ']' | ')' if in_regex => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        current.push(c);
+    }
+    entries.push(current);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_params(values: &[&str]) -> Result<Option<BanWords>, String> {
+        BanWords::from_query(&QString::new(
+            values.iter().map(|v| ("ban_words", *v)).collect(),
+        ))
+    }
+
+    #[test]
+    fn test_whole_words_and_regexes() {
+        let ban_words = from_params(&["vfq, cam,web-dl,re:\\bs0[1-3]e"])
+            .unwrap()
+            .unwrap();
+        assert!(ban_words.is_banned("Film.2024.VFQ.1080p"));
+        assert!(ban_words.is_banned("Film 2024 CAM x264"));
+        assert!(ban_words.is_banned("Film.2024.WEB.DL.1080p"));
+        assert!(ban_words.is_banned("Show.S02E01.1080p"));
+        assert!(!ban_words.is_banned("Camera.Obscura.2024.1080p"));
+        assert!(!ban_words.is_banned("Film.2024.VFQX.1080p"));
+        assert!(!ban_words.is_banned("Show.S04E01.1080p"));
+
+        assert!(from_params(&["re:("]).is_err());
+        assert!(from_params(&[" , "]).unwrap().is_none());
+        assert!(from_params(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_split_param() {
+        assert_eq!(
+            split_param("re:\\bS\\d{1,2}E,vfq , re:(a,b)|[x,y],re:a\\,b,c{1,2}"),
+            vec![
+                "re:\\bS\\d{1,2}E",
+                "vfq ",
+                " re:(a,b)|[x,y]",
+                "re:a\\,b",
+                "c{1",
+                "2}"
+            ]
+        );
+
+        let ban_words = from_params(&["re:\\bS\\d{1,2}E\\d", "cam"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(ban_words.len(), 2);
+        assert!(ban_words.is_banned("Show.S02E01.1080p"));
+        assert!(ban_words.is_banned("Film.CAM.x264"));
+        assert!(!ban_words.is_banned("Show.S123E01.1080p"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+/// A bencoded value. Dictionary keys are kept sorted as raw bytes, which is the order
+/// required by the BitTorrent spec, so re-encoding a well-formed file is lossless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+/// Nesting limit when decoding, protects against stack exhaustion on hostile input.
+const MAX_DEPTH: usize = 64;
+
+impl Bencode {
+    pub fn str(s: &str) -> Self {
+        Bencode::Bytes(s.as_bytes().to_vec())
+    }
+
+    pub fn dict() -> Self {
+        Bencode::Dict(BTreeMap::new())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(map) => map.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// Set a dictionary key, does nothing on other values.
+    pub fn insert(&mut self, key: &str, value: Bencode) {
+        if let Bencode::Dict(map) = self {
+            map.insert(key.as_bytes().to_vec(), value);
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(i) => out.extend_from_slice(format!("i{}e", i).as_bytes()),
+            Bencode::Bytes(b) => {
+                out.extend_from_slice(format!("{}:", b.len()).as_bytes());
+                out.extend_from_slice(b);
+            }
+            Bencode::List(list) => {
+                out.push(b'l');
+                for item in list {
+                    item.encode_into(out);
+                }
+                out.push(b'e');
+            }
+            Bencode::Dict(map) => {
+                out.push(b'd');
+                for (key, value) in map {
+                    out.extend_from_slice(format!("{}:", key.len()).as_bytes());
+                    out.extend_from_slice(key);
+                    value.encode_into(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+
+    /// Decode a complete bencoded document. Trailing data is an error.
+    pub fn decode(data: &[u8]) -> Result<Bencode, Box<dyn std::error::Error + Send + Sync>> {
+        let mut pos = 0;
+        let value = decode_at(data, &mut pos, 0)?;
+        if pos != data.len() {
+            return Err(format!("Trailing data after bencoded value at byte {}", pos).into());
+        }
+        Ok(value)
+    }
+}
+
+fn decode_at(
+    data: &[u8],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Bencode, Box<dyn std::error::Error + Send + Sync>> {
+    if depth > MAX_DEPTH {
+        return Err("Bencode nesting too deep".into());
+    }
+    match data.get(*pos) {
+        Some(b'i') => {
+            *pos += 1;
+            let raw = read_until(data, pos, b'e')?;
+            let int = std::str::from_utf8(raw)?.parse::<i64>()?;
+            Ok(Bencode::Int(int))
+        }
+        Some(b'l') => {
+            *pos += 1;
+            let mut list = Vec::new();
+            while data.get(*pos) != Some(&b'e') {
+                list.push(decode_at(data, pos, depth + 1)?);
+            }
+            *pos += 1;
+            Ok(Bencode::List(list))
+        }
+        Some(b'd') => {
+            *pos += 1;
+            let mut map = BTreeMap::new();
+            while data.get(*pos) != Some(&b'e') {
+                let key = read_bytes(data, pos)?;
+                let value = decode_at(data, pos, depth + 1)?;
+                map.insert(key, value);
+            }
+            *pos += 1;
+            Ok(Bencode::Dict(map))
+        }
+        Some(b'0'..=b'9') => Ok(Bencode::Bytes(read_bytes(data, pos)?)),
+        Some(c) => Err(format!("Unexpected byte {:?} at {}", *c as char, pos).into()),
+        None => Err("Unexpected end of bencoded data".into()),
+    }
+}
+
+/// `<len>:<bytes>`
+fn read_bytes(
+    data: &[u8],
+    pos: &mut usize,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let len = std::str::from_utf8(read_until(data, pos, b':')?)?.parse::<usize>()?;
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or("Bencoded string runs past the end of the data")?;
+    let bytes = data[*pos..end].to_vec();
+    *pos = end;
+    Ok(bytes)
+}
+
+fn read_until<'a>(
+    data: &'a [u8],
+    pos: &mut usize,
+    delimiter: u8,
+) -> Result<&'a [u8], Box<dyn std::error::Error + Send + Sync>> {
+    let start = *pos;
+    let len = data[start..]
+        .iter()
+        .position(|b| *b == delimiter)
+        .ok_or("Unterminated bencoded value")?;
+    *pos = start + len + 1;
+    Ok(&data[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_fixture() {
+        let data = std::fs::read("tests/test.torrent").unwrap();
+        let torrent = Bencode::decode(&data).unwrap();
+        assert_eq!(torrent.encode(), data);
+        assert!(torrent.get("info").and_then(|i| i.get("pieces")).is_some());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(Bencode::decode(b"i42e").is_ok());
+        assert!(Bencode::decode(b"i42").is_err());
+        assert!(Bencode::decode(b"5:abc").is_err());
+        assert!(Bencode::decode(b"le extra").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::config::Config;
+use crate::nostr::SearchBatch;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+static SEARCH_CACHE: OnceLock<SearchCache> = OnceLock::new();
+
+/// The relay search cache, with the default settings until `init_search_cache` ran.
+pub fn get_search_cache() -> &'static SearchCache {
+    SEARCH_CACHE.get_or_init(|| {
+        let config = Config::default();
+        SearchCache::new(
+            Duration::from_secs(config.cache_ttl),
+            config.cache_max_entries,
+        )
+    })
+}
+
+pub fn init_search_cache(ttl: Duration, max_entries: usize) {
+    if SEARCH_CACHE
+        .set(SearchCache::new(ttl, max_entries))
+        .is_err()
+    {
+        warn!("Search cache already initialized");
+    }
+}
+
+/// A relay search, normalized so that equivalent queries share an entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    query: String,
+    /// `#i` tag values, for external ID searches
+    external_ids: Vec<String>,
+    tag_filter: Option<String>,
+    limit: usize,
+    since: Option<u64>,
+    until: Option<u64>,
+}
+
+impl CacheKey {
+    pub fn new(
+        query: &str,
+        tag_filter: Option<&str>,
+        limit: usize,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Self {
+        CacheKey {
+            query: query
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase(),
+            external_ids: Vec::new(),
+            tag_filter: tag_filter.map(|t| t.to_lowercase()),
+            limit,
+            since,
+            until,
+        }
+    }
+
+    /// A search on `i` tags instead of text.
+    pub fn external_ids(external_ids: &[String], tag_filter: Option<&str>, limit: usize) -> Self {
+        let mut external_ids = external_ids.to_vec();
+        external_ids.sort();
+        CacheKey {
+            external_ids,
+            ..CacheKey::new("", tag_filter, limit, None, None)
+        }
+    }
+}
+
+struct Slot {
+    created: Instant,
+    value: OnceCell<SearchBatch>,
+}
+
+/// Relay search results kept for `ttl`. Identical searches running at the same time share
+/// the same slot, so only the first one reaches the relay.
+pub struct SearchCache {
+    ttl: Duration,
+    max_entries: usize,
+    slots: Mutex<HashMap<CacheKey, Arc<Slot>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl SearchCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        SearchCache {
+            ttl,
+            max_entries,
+            slots: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the cached batch for `key`, or run `fetch` once for every concurrent caller.
+    /// Failed fetches are not cached.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        key: CacheKey,
+        fetch: F,
+    ) -> Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>>>,
+    {
+        let slot = {
+            let mut slots = self.slots.lock().unwrap();
+            match slots.get(&key) {
+                Some(slot) if slot.value.initialized() && !self.expired(slot) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(slot.value.get().unwrap().clone());
+                }
+                Some(slot) if !slot.value.initialized() => {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    slot.clone()
+                }
+                _ => {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                    self.evict(&mut slots);
+                    let slot = Arc::new(Slot {
+                        created: Instant::now(),
+                        value: OnceCell::new(),
+                    });
+                    slots.insert(key.clone(), slot.clone());
+                    slot
+                }
+            }
+        };
+
+        match slot.value.get_or_try_init(fetch).await {
+            Ok(batch) => Ok(batch.clone()),
+            Err(e) => {
+                let mut slots = self.slots.lock().unwrap();
+                if slots.get(&key).is_some_and(|s| Arc::ptr_eq(s, &slot)) {
+                    slots.remove(&key);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn expired(&self, slot: &Slot) -> bool {
+        slot.created.elapsed() >= self.ttl
+    }
+
+    /// Drop expired entries, then the oldest ones until there is room for a new entry.
+    fn evict(&self, slots: &mut HashMap<CacheKey, Arc<Slot>>) {
+        slots.retain(|_, slot| !slot.value.initialized() || !self.expired(slot));
+        while slots.len() >= self.max_entries.max(1) {
+            let Some(oldest) = slots
+                .iter()
+                .filter(|(_, slot)| slot.value.initialized())
+                .min_by_key(|(_, slot)| slot.created)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            slots.remove(&oldest);
+        }
+    }
+
+    pub fn stats(&self) -> serde_json::Value {
+        serde_json::json!({
+            "entries": self.slots.lock().unwrap().len(),
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "coalesced": self.coalesced.load(Ordering::Relaxed),
+            "ttl": self.ttl.as_secs(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(count: usize) -> SearchBatch {
+        SearchBatch {
+            torrents: Vec::new(),
+            event_count: count,
+            oldest_created_at: None,
+            created_at: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_searches_are_coalesced() {
+        let cache = SearchCache::new(Duration::from_secs(60), 10);
+        let calls = AtomicU64::new(0);
+        let fetch = || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(batch(1))
+        };
+
+        let (a, b) = tokio::join!(
+            cache.get_or_fetch(CacheKey::new("Vaiana  2", None, 100, None, None), fetch),
+            cache.get_or_fetch(CacheKey::new("vaiana 2", None, 100, None, None), fetch),
+        );
+        assert!(a.is_ok() && b.is_ok());
+        let cached = cache
+            .get_or_fetch(CacheKey::new("vaiana 2", None, 100, None, None), fetch)
+            .await;
+        assert_eq!(cached.unwrap().event_count, 1);
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.coalesced.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_size_bound() {
+        let cache = SearchCache::new(Duration::from_secs(60), 2);
+        for query in ["a", "b", "c"] {
+            let key = CacheKey::new(query, None, 100, None, None);
+            cache
+                .get_or_fetch(key, || async { Ok(batch(0)) })
+                .await
+                .unwrap();
+        }
+        assert_eq!(cache.slots.lock().unwrap().len(), 2);
+    }
+}
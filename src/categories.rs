@@ -144,6 +144,100 @@ pub fn cat_id_to_nostr_tag(id: usize) -> Option<&'static str> {
     Some(tag)
 }
 
+/// Map a numeric category ID to its Newznab standard category (matching ygege.yml).
+/// Unknown IDs map to `8000` (Other).
+pub fn cat_id_to_newznab(id: usize) -> usize {
+    match id {
+        2145 => 5000,
+        2178 => 2020,
+        2179 => 5070,
+        2180 => 3020,
+        2181 => 5080,
+        2182 => 5000,
+        2183 => 2000,
+        2184 => 5000,
+        2185 => 5000,
+        2186 => 5060,
+        2187 => 5050,
+        2139 | 2147 | 2148 | 2150 => 3000,
+        2149 => 3050,
+        2144 | 2176 => 4000,
+        2177 | 2173 => 4010,
+        2171 => 4020,
+        2172 => 4030,
+        2174 | 2175 | 2165 | 2166 => 4070,
+        2142 | 2159 | 2160 | 2161 => 4050,
+        2167 => 1090,
+        2162 => 1140,
+        2163 => 1030,
+        2164 => 1180,
+        2140 => 7000,
+        2151 => 3030,
+        2152 | 2154 => 7020,
+        2153 | 2155 => 7030,
+        2156 => 7010,
+        2188..=2190 => 6000,
+        2191 => 6060,
+        _ => 8000,
+    }
+}
+
+/// Display name of a Newznab standard category.
+pub fn newznab_name(id: usize) -> &'static str {
+    match id {
+        1000 => "Console",
+        1030 => "Console/Wii",
+        1090 => "Console/Other",
+        1140 => "Console/XBox One",
+        1180 => "Console/PS4",
+        2000 => "Movies",
+        2020 => "Movies/Other",
+        3000 => "Audio",
+        3020 => "Audio/Video",
+        3030 => "Audio/Audiobook",
+        3050 => "Audio/Other",
+        4000 => "PC",
+        4010 => "PC/0day",
+        4020 => "PC/ISO",
+        4030 => "PC/Mac",
+        4050 => "PC/Games",
+        4070 => "PC/Mobile-Android",
+        5000 => "TV",
+        5050 => "TV/Other",
+        5060 => "TV/Sport",
+        5070 => "TV/Anime",
+        5080 => "TV/Documentary",
+        6000 => "XXX",
+        6060 => "XXX/ImageSet",
+        7000 => "Books",
+        7010 => "Books/Mags",
+        7020 => "Books/EBook",
+        7030 => "Books/Comics",
+        _ => "Other",
+    }
+}
+
+/// Offset added to site category IDs when exposed as Torznab custom categories.
+pub const TORZNAB_CUSTOM_OFFSET: usize = 100_000;
+
+/// Resolve a Torznab `cat` value (Newznab standard or custom) to site category IDs.
+/// A Newznab parent (e.g. `5000`) also matches every site category mapped to one of its subcategories.
+pub fn newznab_to_cat_ids(id: usize) -> Vec<usize> {
+    if id >= TORZNAB_CUSTOM_OFFSET {
+        return vec![id - TORZNAB_CUSTOM_OFFSET];
+    }
+    let is_parent = id.is_multiple_of(1000);
+    init_categories()
+        .iter()
+        .flat_map(|c| std::iter::once(c).chain(c.sub_categories.iter()))
+        .map(|c| c.id)
+        .filter(|&cat_id| {
+            let mapped = cat_id_to_newznab(cat_id);
+            mapped == id || (is_parent && mapped / 1000 * 1000 == id)
+        })
+        .collect()
+}
+
 pub fn init_categories() -> Vec<Category> {
     vec![
         Category {
@@ -1,8 +1,20 @@
+use crate::auth::ApiKey;
+use crate::download_client::DownloadClient;
+use crate::ranking::RankingProfile;
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 const CONFIG_PATH: &str = "config.json";
 
+/// Path of a data file stored next to `config.json`.
+pub fn data_path(file_name: &str) -> std::path::PathBuf {
+    std::path::Path::new(CONFIG_PATH)
+        .parent()
+        .unwrap_or(std::path::Path::new(""))
+        .join(file_name)
+}
+
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     if let Ok(config) = load_config_from_env() {
         return Ok(config);
@@ -32,6 +44,27 @@ fn load_config_from_env() -> Result<Config, std::io::Error> {
         "TMDB_TOKEN",
         "USE_TOR",
         "TOR_PROXY",
+        "LOCAL_INDEX",
+        "INDEX_PATH",
+        "RELAY_MODE",
+        "FANOUT_RELAYS",
+        "TORRENT_CACHE_URLS",
+        "TRACKER_MODE",
+        "TRACKERS",
+        "TRACKERS_SOURCE",
+        "PRUNE_TRACKERS",
+        "CACHE_TTL",
+        "CACHE_MAX_ENTRIES",
+        "TMDB_CACHE_TTL",
+        "TMDB_LANGUAGE",
+        "TMDB_REGIONS",
+        "TMDB_TRANSLATIONS",
+        "TMDB_MAX_QUERIES",
+        "IMDB_DATASET_PATH",
+        "BAN_WORDS",
+        "RANKING_PROFILES",
+        "DOWNLOAD_CLIENTS",
+        "API_KEYS",
     ];
     if !ENV_KEYS.iter().any(|k| std::env::var(k).is_ok()) {
         return Err(std::io::Error::new(
@@ -80,6 +113,173 @@ fn load_config_from_env() -> Result<Config, std::io::Error> {
             }
         });
 
+    let local_index = std::env::var("LOCAL_INDEX")
+        .unwrap_or("false".to_string())
+        .to_lowercase()
+        == "true";
+
+    let index_path = std::env::var("INDEX_PATH")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(default_index_path);
+
+    let relay_mode = match std::env::var("RELAY_MODE") {
+        Ok(mode) => mode
+            .parse::<RelayMode>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        Err(_) => RelayMode::default(),
+    };
+
+    let fanout_relays = match std::env::var("FANOUT_RELAYS") {
+        Ok(n) => n.parse::<usize>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "FANOUT_RELAYS must be a positive number",
+            )
+        })?,
+        Err(_) => default_fanout_relays(),
+    };
+
+    let torrent_cache_urls = std::env::var("TORRENT_CACHE_URLS")
+        .map(|urls| {
+            urls.split(',')
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let tracker_mode = match std::env::var("TRACKER_MODE") {
+        Ok(mode) => mode
+            .parse::<TrackerMode>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        Err(_) => TrackerMode::default(),
+    };
+
+    let trackers = std::env::var("TRACKERS")
+        .map(|trackers| {
+            trackers
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let trackers_source = std::env::var("TRACKERS_SOURCE")
+        .ok()
+        .filter(|s| !s.is_empty());
+
+    let prune_trackers = std::env::var("PRUNE_TRACKERS")
+        .unwrap_or("false".to_string())
+        .to_lowercase()
+        == "true";
+
+    let cache_ttl = match std::env::var("CACHE_TTL") {
+        Ok(n) => n.parse::<u64>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "CACHE_TTL must be a number of seconds",
+            )
+        })?,
+        Err(_) => default_cache_ttl(),
+    };
+
+    let cache_max_entries = match std::env::var("CACHE_MAX_ENTRIES") {
+        Ok(n) => n.parse::<usize>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "CACHE_MAX_ENTRIES must be a positive number",
+            )
+        })?,
+        Err(_) => default_cache_max_entries(),
+    };
+
+    let tmdb_cache_ttl = match std::env::var("TMDB_CACHE_TTL") {
+        Ok(n) => n.parse::<u64>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "TMDB_CACHE_TTL must be a number of seconds",
+            )
+        })?,
+        Err(_) => default_tmdb_cache_ttl(),
+    };
+
+    let tmdb_language = std::env::var("TMDB_LANGUAGE")
+        .ok()
+        .filter(|l| !l.is_empty())
+        .unwrap_or_else(default_tmdb_language);
+
+    let tmdb_regions = std::env::var("TMDB_REGIONS")
+        .map(|regions| {
+            regions
+                .split(',')
+                .map(|r| r.trim().to_uppercase())
+                .filter(|r| !r.is_empty())
+                .collect()
+        })
+        .unwrap_or_else(|_| default_tmdb_regions());
+
+    let tmdb_translations = std::env::var("TMDB_TRANSLATIONS")
+        .unwrap_or("true".to_string())
+        .to_lowercase()
+        == "true";
+
+    let tmdb_max_queries = match std::env::var("TMDB_MAX_QUERIES") {
+        Ok(n) => n.parse::<usize>().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "TMDB_MAX_QUERIES must be a positive number",
+            )
+        })?,
+        Err(_) => default_tmdb_max_queries(),
+    };
+
+    let imdb_dataset_path = std::env::var("IMDB_DATASET_PATH")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(default_imdb_dataset_path);
+
+    let ban_words = std::env::var("BAN_WORDS")
+        .map(|words| {
+            words
+                .split(',')
+                .map(|w| w.trim().to_string())
+                .filter(|w| !w.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let ranking_profiles = match std::env::var("RANKING_PROFILES") {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid RANKING_PROFILES value: {}", e),
+            )
+        })?,
+        Err(_) => BTreeMap::new(),
+    };
+
+    let download_clients = match std::env::var("DOWNLOAD_CLIENTS") {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid DOWNLOAD_CLIENTS value: {}", e),
+            )
+        })?,
+        Err(_) => BTreeMap::new(),
+    };
+
+    let api_keys = match std::env::var("API_KEYS") {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid API_KEYS value: {}", e),
+            )
+        })?,
+        Err(_) => Vec::new(),
+    };
+
     Ok(Config {
         bind_ip,
         bind_port,
@@ -87,6 +287,27 @@ fn load_config_from_env() -> Result<Config, std::io::Error> {
         tmdb_token,
         use_tor,
         tor_proxy,
+        local_index,
+        index_path,
+        relay_mode,
+        fanout_relays,
+        torrent_cache_urls,
+        tracker_mode,
+        trackers,
+        trackers_source,
+        prune_trackers,
+        cache_ttl,
+        cache_max_entries,
+        tmdb_cache_ttl,
+        tmdb_language,
+        tmdb_regions,
+        tmdb_translations,
+        tmdb_max_queries,
+        imdb_dataset_path,
+        ban_words,
+        ranking_profiles,
+        download_clients,
+        api_keys,
     })
 }
 
@@ -101,12 +322,170 @@ pub struct Config {
     pub use_tor: bool,
     #[serde(default)]
     pub tor_proxy: Option<String>,
+    #[serde(default)]
+    pub local_index: bool,
+    #[serde(default = "default_index_path")]
+    pub index_path: String,
+    #[serde(default)]
+    pub relay_mode: RelayMode,
+    #[serde(default = "default_fanout_relays")]
+    pub fanout_relays: usize,
+    /// URL templates of `.torrent` caches, `{infohash}` is replaced by the torrent hash
+    #[serde(default)]
+    pub torrent_cache_urls: Vec<String>,
+    #[serde(default)]
+    pub tracker_mode: TrackerMode,
+    /// Custom trackers, used in `custom` and `merge` modes
+    #[serde(default)]
+    pub trackers: Vec<String>,
+    /// File path or URL of a tracker list, one announce URL per line
+    #[serde(default)]
+    pub trackers_source: Option<String>,
+    #[serde(default)]
+    pub prune_trackers: bool,
+    /// Seconds a relay search result is reused, 0 only coalesces concurrent searches
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+    #[serde(default = "default_cache_max_entries")]
+    pub cache_max_entries: usize,
+    /// Seconds resolved TMDB/IMDB titles are kept on disk, 0 disables the cache
+    #[serde(default = "default_tmdb_cache_ttl")]
+    pub tmdb_cache_ttl: u64,
+    /// TMDB `language` parameter, the region of the returned title ranks it among `tmdb_regions`
+    #[serde(default = "default_tmdb_language")]
+    pub tmdb_language: String,
+    /// ISO 3166-1 countries whose alternative titles and translations are searched, most likely first
+    #[serde(default = "default_tmdb_regions")]
+    pub tmdb_regions: Vec<String>,
+    #[serde(default = "default_tmdb_translations")]
+    pub tmdb_translations: bool,
+    /// Maximum number of title queries searched for one TMDB/IMDB/TVDB ID
+    #[serde(default = "default_tmdb_max_queries")]
+    pub tmdb_max_queries: usize,
+    /// Titles imported from the IMDb datasets with `--import-imdb`, used without a TMDB token
+    #[serde(default = "default_imdb_dataset_path")]
+    pub imdb_dataset_path: String,
+    /// Words removed from every search, Torznab and RSS result, on top of the `ban_words`
+    /// query parameter. `re:` entries are regexes.
+    #[serde(default)]
+    pub ban_words: Vec<String>,
+    /// Named `sort=relevance` profiles, `default` overrides the built-in one
+    #[serde(default)]
+    pub ranking_profiles: BTreeMap<String, RankingProfile>,
+    /// qBittorrent, Transmission and Deluge instances `/torrent/{id}/send` can add magnets to
+    #[serde(default)]
+    pub download_clients: BTreeMap<String, DownloadClient>,
+    /// Keys accepted in the `apikey` parameter or the `X-Api-Key` header, none disables
+    /// authentication
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+}
+
+/// Which trackers are added to magnet links and `.torrent` files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackerMode {
+    /// The trackers listed in the event, the built-in list when it has none
+    Event,
+    /// The list used by ygg.gratis
+    #[default]
+    Builtin,
+    /// `trackers` and `trackers_source` only
+    Custom,
+    /// The event trackers, then the custom list, then the built-in one
+    Merge,
+}
+
+impl std::str::FromStr for TrackerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "event" => Ok(TrackerMode::Event),
+            "builtin" => Ok(TrackerMode::Builtin),
+            "custom" => Ok(TrackerMode::Custom),
+            "merge" => Ok(TrackerMode::Merge),
+            _ => Err(format!(
+                "TRACKER_MODE must be one of event, builtin, custom, merge (got {})",
+                s
+            )),
+        }
+    }
+}
+
+/// How a REQ is dispatched across the ranked relays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayMode {
+    /// Ask the best relay only, fail over to the next one on error
+    #[default]
+    First,
+    /// Ask the top relays in parallel and keep the first non-empty answer
+    Fastest,
+    /// Ask the top relays in parallel and merge every answer
+    All,
+}
+
+impl std::str::FromStr for RelayMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "first" => Ok(RelayMode::First),
+            "fastest" => Ok(RelayMode::Fastest),
+            "all" => Ok(RelayMode::All),
+            _ => Err(format!(
+                "RELAY_MODE must be one of first, fastest, all (got {})",
+                s
+            )),
+        }
+    }
 }
 
 fn default_use_tor() -> bool {
     false
 }
 
+fn default_index_path() -> String {
+    "index.jsonl".to_string()
+}
+
+fn default_fanout_relays() -> usize {
+    3
+}
+
+fn default_cache_ttl() -> u64 {
+    120
+}
+
+fn default_cache_max_entries() -> usize {
+    1000
+}
+
+fn default_tmdb_cache_ttl() -> u64 {
+    7 * 24 * 3600
+}
+
+fn default_tmdb_language() -> String {
+    "en-US".to_string()
+}
+
+fn default_tmdb_regions() -> Vec<String> {
+    ["FR", "US", "GB"].iter().map(|r| r.to_string()).collect()
+}
+
+fn default_tmdb_translations() -> bool {
+    true
+}
+
+fn default_tmdb_max_queries() -> usize {
+    10
+}
+
+fn default_imdb_dataset_path() -> String {
+    "imdb_titles.tsv".to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -116,6 +495,27 @@ impl Default for Config {
             tmdb_token: None,
             use_tor: false,
             tor_proxy: Some("127.0.0.1:9050".to_string()),
+            local_index: false,
+            index_path: default_index_path(),
+            relay_mode: RelayMode::First,
+            fanout_relays: default_fanout_relays(),
+            torrent_cache_urls: Vec::new(),
+            tracker_mode: TrackerMode::Builtin,
+            trackers: Vec::new(),
+            trackers_source: None,
+            prune_trackers: false,
+            cache_ttl: default_cache_ttl(),
+            cache_max_entries: default_cache_max_entries(),
+            tmdb_cache_ttl: default_tmdb_cache_ttl(),
+            tmdb_language: default_tmdb_language(),
+            tmdb_regions: default_tmdb_regions(),
+            tmdb_translations: default_tmdb_translations(),
+            tmdb_max_queries: default_tmdb_max_queries(),
+            imdb_dataset_path: default_imdb_dataset_path(),
+            ban_words: Vec::new(),
+            ranking_profiles: BTreeMap::new(),
+            download_clients: BTreeMap::new(),
+            api_keys: Vec::new(),
         }
     }
 }
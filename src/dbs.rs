@@ -1,9 +1,21 @@
+use crate::config::Config;
+use crate::imdb_dataset::ImdbDataset;
+use crate::tmdb_cache::get_tmdb_cache;
+use futures::future::LocalBoxFuture;
 use reqwest::Client;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// HTTP client shared by every TMDB request.
+fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(Client::new)
+}
 
 pub async fn get_account_username(token: &String) -> Result<String, Box<dyn std::error::Error>> {
     debug!("Fetching TMDB account username");
-    let client = Client::new();
-    let response = client
+    let response = http_client()
         .get("https://api.themoviedb.org/3/account")
         .header("Authorization", format!("Bearer {}", token))
         .header("accept", "application/json")
@@ -24,9 +36,47 @@ pub async fn get_account_username(token: &String) -> Result<String, Box<dyn std:
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DbQueryType {
     TMDB,
     IMDB,
+    /// TMDB ID of a TV show
+    TMDBTV,
+    TVDB,
+}
+
+impl DbQueryType {
+    fn name(&self) -> &'static str {
+        match self {
+            DbQueryType::TMDB => "tmdb",
+            DbQueryType::IMDB => "imdb",
+            DbQueryType::TMDBTV => "tmdb_tv",
+            DbQueryType::TVDB => "tvdb",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaKind {
+    Movie,
+    Tv,
+}
+
+impl MediaKind {
+    fn path(&self) -> &'static str {
+        match self {
+            MediaKind::Movie => "movie",
+            MediaKind::Tv => "tv",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            MediaKind::Movie => "movie",
+            MediaKind::Tv => "TV show",
+        }
+    }
 }
 
 const ALLOWED_CHARS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_. ";
@@ -93,128 +143,533 @@ fn fix_title(title: &str) -> String {
         .chars()
         .filter(|c| ALLOWED_CHARS.contains(*c))
         .collect::<String>()
-        .trim()
-        .to_string()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-pub async fn get_queries(
-    id: String,
+async fn tmdb_get(
     token: &String,
-    db_type: DbQueryType,
-) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    debug!("Fetching TMDB titles for ID: {}", id);
-    let client = Client::new();
-    let url = match db_type {
-        DbQueryType::TMDB => format!("https://api.themoviedb.org/3/movie/{}", id),
-        DbQueryType::IMDB => format!(
-            "https://api.themoviedb.org/3/find/{}?external_source=imdb_id",
-            id
-        ),
-    };
-    let response = client
-        .get(&url)
+    url: &str,
+    language: &str,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut url = url::Url::parse(url)?;
+    url.query_pairs_mut().append_pair("language", language);
+    let response = http_client()
+        .get(url)
         .header("Authorization", format!("Bearer {}", token))
         .header("accept", "application/json")
         .send()
         .await?;
 
+    if response.status().as_u16() == 404 {
+        return Err("Not found".into());
+    }
     if !response.status().is_success() {
-        // 404
-        if response.status().as_u16() == 404 {
-            return Err("TMDB movie not found".into());
-        }
-
-        return Err(format!("Failed to fetch TMDB movie info: {}", response.status()).into());
+        return Err(format!("TMDB returned {}", response.status()).into());
     }
 
     let body = response.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&body)?;
+    Ok(serde_json::from_str(&body)?)
+}
 
-    let id = json
-        .get("id")
-        .and_then(|i| i.as_u64())
-        .ok_or("ID not found in TMDB response")?;
+/// Resolve an external ID to a TMDB movie or TV show through `/3/find`.
+async fn find_by_external_id(
+    token: &String,
+    id: &str,
+    source: &str,
+    language: &str,
+) -> Result<(MediaKind, String), Box<dyn std::error::Error>> {
+    let url = format!(
+        "https://api.themoviedb.org/3/find/{}?external_source={}",
+        id, source
+    );
+    let json = tmdb_get(token, &url, language)
+        .await
+        .map_err(|e| format!("Failed to find {} {} on TMDB: {}", source, id, e))?;
 
-    let year = json
-        .get("release_date")
-        .and_then(|rd| rd.as_str())
-        .and_then(|date_str| date_str.split('-').next())
-        .and_then(|year_str| year_str.parse::<u32>().ok())
-        .unwrap_or(0);
-
-    let original_title = json
-        .get("original_title")
-        .and_then(|ot| ot.as_str())
-        .ok_or("Original title not found in TMDB response")?
-        .to_string();
-
-    let title = json
-        .get("title")
-        .and_then(|t| t.as_str())
-        .ok_or("Title not found in TMDB response")?
-        .to_string();
-
-    let mut titles = Vec::new();
-    if original_title != title {
-        let original_title = fix_title(&original_title);
-        if !original_title.is_empty() {
-            titles.push(format!("{} {}", original_title, year));
+    find_result(&json).ok_or_else(|| format!("No TMDB entry found for {} {}", source, id).into())
+}
+
+/// First entry of a `/3/find` response, movies before TV shows.
+fn find_result(json: &Value) -> Option<(MediaKind, String)> {
+    let first_id = |key: &str| -> Option<String> {
+        json.get(key)?
+            .as_array()?
+            .first()?
+            .get("id")?
+            .as_u64()
+            .map(|id| id.to_string())
+    };
+    first_id("movie_results")
+        .map(|id| (MediaKind::Movie, id))
+        .or_else(|| first_id("tv_results").map(|id| (MediaKind::Tv, id)))
+}
+
+/// Turns a database ID into search titles, most likely release title first.
+pub trait TitleResolver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn supports(&self, db_type: DbQueryType) -> bool;
+
+    fn titles<'a>(
+        &'a self,
+        id: &'a str,
+        db_type: DbQueryType,
+        config: &'a Config,
+    ) -> LocalBoxFuture<'a, Result<Vec<String>, Box<dyn std::error::Error>>>;
+}
+
+static RESOLVERS: OnceLock<Vec<Box<dyn TitleResolver>>> = OnceLock::new();
+
+fn resolvers() -> &'static [Box<dyn TitleResolver>] {
+    RESOLVERS.get().map(|r| r.as_slice()).unwrap_or_default()
+}
+
+/// Register the available resolvers: TMDB when a token is configured, then the imported
+/// IMDb dataset as a fallback.
+pub fn init_resolvers(config: &Config) {
+    let mut list: Vec<Box<dyn TitleResolver>> = Vec::new();
+    if let Some(token) = &config.tmdb_token {
+        list.push(Box::new(TmdbResolver {
+            token: token.clone(),
+        }));
+    }
+    if let Some(dataset) = ImdbDataset::open(&config.imdb_dataset_path) {
+        info!(
+            "Offline IMDb resolver enabled ({})",
+            dataset.path().display()
+        );
+        list.push(Box::new(ImdbDatasetResolver { dataset }));
+    }
+    if RESOLVERS.set(list).is_err() {
+        warn!("Title resolvers already initialized");
+    }
+}
+
+/// Names of the registered resolvers.
+pub fn resolver_names() -> Vec<&'static str> {
+    resolvers().iter().map(|r| r.name()).collect()
+}
+
+/// Whether an ID of this type can be turned into titles.
+pub fn can_resolve(db_type: DbQueryType) -> bool {
+    resolvers().iter().any(|r| r.supports(db_type))
+}
+
+/// Build the title queries of a movie or a TV show with the first resolver that knows it.
+/// A TMDB ID is looked up as a movie, then as a TV show: requests without a season do not
+/// say which one they mean.
+pub async fn get_queries(
+    id: String,
+    config: &Config,
+    db_type: DbQueryType,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    match resolve(&id, config, db_type).await {
+        Err(e) if db_type == DbQueryType::TMDB && can_resolve(DbQueryType::TMDBTV) => {
+            debug!("No movie for TMDB ID {} ({}), trying a TV show", id, e);
+            resolve(&id, config, DbQueryType::TMDBTV).await
         }
+        result => result,
     }
-    let title = fix_title(&title);
-    if !title.is_empty() {
-        titles.push(format!("{} {}", title, year));
+}
+
+async fn resolve(
+    id: &str,
+    config: &Config,
+    db_type: DbQueryType,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut last_error: Option<Box<dyn std::error::Error>> = None;
+    for resolver in resolvers().iter().filter(|r| r.supports(db_type)) {
+        match resolver.titles(id, db_type, config).await {
+            Ok(mut titles) if !titles.is_empty() => {
+                titles.truncate(config.tmdb_max_queries.max(1));
+                return Ok(titles);
+            }
+            Ok(_) => debug!(
+                "{} has no title for {} {}",
+                resolver.name(),
+                db_type.name(),
+                id
+            ),
+            Err(e) => {
+                debug!(
+                    "{} failed to resolve {} {}: {}",
+                    resolver.name(),
+                    db_type.name(),
+                    id,
+                    e
+                );
+                last_error = Some(e);
+            }
+        }
     }
+    Err(last_error
+        .unwrap_or_else(|| format!("No title found for {} {}", db_type.name(), id).into()))
+}
 
-    let alt_url = format!(
-        "https://api.themoviedb.org/3/movie/{}/alternative_titles",
-        id
-    );
-    let alt_response = client
-        .get(&alt_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .header("accept", "application/json")
-        .send()
-        .await?;
+/// Titles from the TMDB API, cached on disk.
+struct TmdbResolver {
+    token: String,
+}
 
-    if !alt_response.status().is_success() {
-        return Err(format!(
-            "Failed to fetch TMDB alternative titles: {}",
-            alt_response.status()
-        )
-        .into());
+impl TitleResolver for TmdbResolver {
+    fn name(&self) -> &'static str {
+        "TMDB"
     }
 
-    let body = alt_response.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&body)?;
-    if let Some(titles_array) = json.get("titles").and_then(|t| t.as_array()) {
-        for title_entry in titles_array {
-            if let Some(iso_3166_1) = title_entry.get("iso_3166_1").and_then(|c| c.as_str()) {
-                if iso_3166_1 == "FR"
-                    || iso_3166_1 == "US"
-                    || iso_3166_1 == "GB"
-                    || iso_3166_1 == "EN"
-                {
-                    if let Some(title) = title_entry.get("title").and_then(|t| t.as_str()) {
-                        if !titles.contains(&title.to_string()) {
-                            let title = match title.ends_with("1") {
-                                true => title.trim_end_matches("1").trim(),
-                                false => title,
-                            };
-                            let title = match title.starts_with("1") {
-                                true => title.trim_start_matches("1").trim(),
-                                false => title,
-                            };
-                            let title = fix_title(title);
-                            if !title.is_empty() {
-                                titles.push(format!("{} {}", title, year));
-                            }
-                        }
-                    }
+    fn supports(&self, _db_type: DbQueryType) -> bool {
+        true
+    }
+
+    fn titles<'a>(
+        &'a self,
+        id: &'a str,
+        db_type: DbQueryType,
+        config: &'a Config,
+    ) -> LocalBoxFuture<'a, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let cache = get_tmdb_cache();
+            let variant = cache_variant(config);
+            let db_name = db_type.name();
+
+            if let Some(titles) = cache.and_then(|c| c.get(db_name, id, &variant)) {
+                debug!("TMDB titles for {} {} served from cache", db_name, id);
+                return Ok(titles);
+            }
+            let titles = fetch_queries(id.to_string(), &self.token, config, db_type).await?;
+            if let Some(cache) = cache {
+                cache.insert(db_name, id, &variant, titles.clone());
+            }
+            Ok(titles)
+        })
+    }
+}
+
+/// Cache variant of a lookup, titles depend on every TMDB setting but `tmdb_max_queries`.
+fn cache_variant(config: &Config) -> String {
+    format!(
+        "{}/{}{}",
+        config.tmdb_language,
+        config.tmdb_regions.join(","),
+        if config.tmdb_translations { "+t" } else { "" }
+    )
+}
+
+/// IMDb IDs resolved offline from the datasets imported with `--import-imdb`.
+struct ImdbDatasetResolver {
+    dataset: ImdbDataset,
+}
+
+impl TitleResolver for ImdbDatasetResolver {
+    fn name(&self) -> &'static str {
+        "IMDb dataset"
+    }
+
+    fn supports(&self, db_type: DbQueryType) -> bool {
+        db_type == DbQueryType::IMDB
+    }
+
+    fn titles<'a>(
+        &'a self,
+        id: &'a str,
+        _db_type: DbQueryType,
+        config: &'a Config,
+    ) -> LocalBoxFuture<'a, Result<Vec<String>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            // Binary search with seeks on the dataset file
+            let dataset = self.dataset.clone();
+            let imdb_id = id.to_string();
+            let title = tokio::task::spawn_blocking(move || {
+                dataset.get(&imdb_id).map_err(|e| e.to_string())
+            })
+            .await??;
+            let Some(title) = title else {
+                return Ok(Vec::new());
+            };
+            let regions = &config.tmdb_regions;
+            let mut candidates = vec![
+                (
+                    ORIGINAL_TITLE_RANK,
+                    TitleSource::Title,
+                    fix_title(&title.primary_title),
+                ),
+                (
+                    ORIGINAL_TITLE_RANK,
+                    TitleSource::Original,
+                    fix_title(&title.original_title),
+                ),
+            ];
+            for aka in &title.akas {
+                if let Some(rank) = region_rank(regions, &aka.region) {
+                    let source = match aka.display {
+                        true => TitleSource::Translation,
+                        false => TitleSource::Alternative,
+                    };
+                    candidates.push((rank, source, clean_alt_title(&aka.title)));
                 }
             }
+            Ok(order_queries(candidates, title.kind, title.year))
+        })
+    }
+}
+
+async fn fetch_queries(
+    id: String,
+    token: &String,
+    config: &Config,
+    db_type: DbQueryType,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    debug!("Fetching TMDB titles for ID: {}", id);
+    let language = config.tmdb_language.as_str();
+    let (kind, tmdb_id) = match db_type {
+        DbQueryType::TMDB => (MediaKind::Movie, id),
+        DbQueryType::TMDBTV => (MediaKind::Tv, id),
+        DbQueryType::IMDB => find_by_external_id(token, &id, "imdb_id", language).await?,
+        DbQueryType::TVDB => find_by_external_id(token, &id, "tvdb_id", language).await?,
+    };
+
+    let mut appended = vec!["alternative_titles"];
+    if config.tmdb_translations {
+        appended.push("translations");
+    }
+    let url = format!(
+        "https://api.themoviedb.org/3/{}/{}?append_to_response={}",
+        kind.path(),
+        tmdb_id,
+        appended.join(",")
+    );
+    let json = tmdb_get(token, &url, language)
+        .await
+        .map_err(|e| format!("Failed to fetch TMDB {} {}: {}", kind.label(), tmdb_id, e))?;
+
+    ranked_titles(&json, kind, config)
+}
+
+/// Where a title comes from. Within a region, translations are the titles releases are
+/// most often named after, alternative titles the least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TitleSource {
+    Translation,
+    Title,
+    Original,
+    Alternative,
+}
+
+/// A possible title: rank of its region in `tmdb_regions`, source, cleaned title.
+type Candidate = (usize, TitleSource, String);
+
+/// Rank of the original and IMDb primary titles, right after the titles of the first region.
+const ORIGINAL_TITLE_RANK: usize = 1;
+
+fn region_rank(regions: &[String], region: &str) -> Option<usize> {
+    regions.iter().position(|r| r.eq_ignore_ascii_case(region))
+}
+
+/// Order candidate titles by confidence: the rank of their region first, then their
+/// source. Movie titles carry the release year, TV show titles are bare since episode
+/// names rarely include it.
+fn order_queries(
+    mut candidates: Vec<Candidate>,
+    kind: MediaKind,
+    year: Option<u32>,
+) -> Vec<String> {
+    let query = |title: &str| match (kind, year) {
+        (MediaKind::Movie, Some(year)) => format!("{} {}", title, year),
+        _ => title.to_string(),
+    };
+
+    // Stable sort, so the order of the source breaks ties
+    candidates.sort_by_key(|(rank, source, _)| (*rank, *source));
+
+    let mut titles: Vec<String> = Vec::new();
+    for (_, _, title) in candidates {
+        if !title.is_empty() && !titles.contains(&query(&title)) {
+            titles.push(query(&title));
         }
     }
+    titles
+}
+
+/// Collect the titles of a TMDB entry. The main title ranks with the region of
+/// `tmdb_language`, the original title right after the first region.
+fn ranked_titles(
+    json: &Value,
+    kind: MediaKind,
+    config: &Config,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let (title_key, original_title_key, date_key) = match kind {
+        MediaKind::Movie => ("title", "original_title", "release_date"),
+        MediaKind::Tv => ("name", "original_name", "first_air_date"),
+    };
+
+    let year = str_field(json, date_key)
+        .and_then(|date_str| date_str.split('-').next())
+        .and_then(|year_str| year_str.parse::<u32>().ok());
+
+    let regions = &config.tmdb_regions;
+    let original_title =
+        str_field(json, original_title_key).ok_or("Original title not found in TMDB response")?;
+    let title = str_field(json, title_key).ok_or("Title not found in TMDB response")?;
+
+    let title_region = config.tmdb_language.split('-').nth(1).unwrap_or("");
+    let mut candidates: Vec<Candidate> = vec![
+        (
+            region_rank(regions, title_region).unwrap_or(regions.len()),
+            TitleSource::Title,
+            fix_title(title),
+        ),
+        (
+            ORIGINAL_TITLE_RANK,
+            TitleSource::Original,
+            fix_title(original_title),
+        ),
+    ];
+
+    // Movies list alternative titles under `titles`, TV shows under `results`
+    let alt_key = match kind {
+        MediaKind::Movie => "titles",
+        MediaKind::Tv => "results",
+    };
+    let alternatives = json
+        .get("alternative_titles")
+        .and_then(|a| a.get(alt_key))
+        .and_then(|t| t.as_array());
+    for entry in alternatives.into_iter().flatten() {
+        let Some(rank) = str_field(entry, "iso_3166_1").and_then(|r| region_rank(regions, r))
+        else {
+            continue;
+        };
+        if let Some(title) = str_field(entry, "title") {
+            candidates.push((rank, TitleSource::Alternative, clean_alt_title(title)));
+        }
+    }
+
+    let translations = json
+        .get("translations")
+        .and_then(|t| t.get("translations"))
+        .and_then(|t| t.as_array());
+    for entry in translations.into_iter().flatten() {
+        let Some(rank) = str_field(entry, "iso_3166_1").and_then(|r| region_rank(regions, r))
+        else {
+            continue;
+        };
+        if let Some(title) = entry.get("data").and_then(|d| str_field(d, title_key)) {
+            candidates.push((rank, TitleSource::Translation, clean_alt_title(title)));
+        }
+    }
+
+    Ok(order_queries(candidates, kind, year))
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(|v| v.as_str())
+}
+
+/// Alternative titles sometimes number the first movie of a franchise ("Saw 1"), which
+/// release names never do. The number is only dropped after a word without digits, so
+/// "1917" or "Ocean's 11" stay as they are.
+fn clean_alt_title(title: &str) -> String {
+    let title = title.trim();
+    let title = match title.rsplit_once(' ') {
+        Some((rest, "1")) if !rest.is_empty() && !rest.chars().any(|c| c.is_ascii_digit()) => rest,
+        _ => title,
+    };
+    fix_title(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranked_titles() {
+        let json = serde_json::json!({
+            "title": "Moana 2",
+            "original_title": "Moana 2",
+            "release_date": "2024-11-21",
+            "alternative_titles": { "titles": [
+                { "iso_3166_1": "DE", "title": "Vaiana 2 DE" },
+                { "iso_3166_1": "US", "title": "Moana 2: The Return" },
+                { "iso_3166_1": "FR", "title": "Vaiana 2 : La Légende du bout du monde" },
+            ]},
+            "translations": { "translations": [
+                { "iso_3166_1": "CA", "iso_639_1": "fr", "data": { "title": "Moana 2" } },
+                { "iso_3166_1": "FR", "iso_639_1": "fr", "data": { "title": "Vaiana 2" } },
+            ]},
+        });
+        let config = Config {
+            tmdb_regions: vec!["FR".into(), "CA".into(), "US".into()],
+            ..Config::default()
+        };
+
+        let titles = ranked_titles(&json, MediaKind::Movie, &config).unwrap();
+        assert_eq!(
+            titles,
+            vec![
+                "Vaiana 2 2024",
+                "Vaiana 2 La Legende du bout du monde 2024",
+                "Moana 2 2024",
+                "Moana 2 The Return 2024",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ranked_tv_titles() {
+        let json = serde_json::json!({
+            "name": "The Office",
+            "original_name": "The Office",
+            "first_air_date": "2005-03-24",
+            "alternative_titles": { "results": [
+                { "iso_3166_1": "FR", "title": "The Office (US)" },
+                { "iso_3166_1": "DE", "title": "Das Büro" },
+            ]},
+            "translations": { "translations": [
+                { "iso_3166_1": "FR", "iso_639_1": "fr", "data": { "name": "Bureau" } },
+            ]},
+        });
+        let config = Config {
+            tmdb_regions: vec!["FR".into(), "US".into()],
+            ..Config::default()
+        };
+
+        // TV show titles carry no year, episode releases never name it
+        let titles = ranked_titles(&json, MediaKind::Tv, &config).unwrap();
+        assert_eq!(titles, vec!["Bureau", "The Office US", "The Office"]);
+    }
+
+    #[test]
+    fn test_find_result() {
+        let json = serde_json::json!({
+            "movie_results": [],
+            "tv_results": [{ "id": 2316, "name": "The Office" }],
+        });
+        assert_eq!(
+            find_result(&json),
+            Some((MediaKind::Tv, "2316".to_string()))
+        );
 
-    Ok(titles)
+        let json = serde_json::json!({
+            "movie_results": [{ "id": 693134 }],
+            "tv_results": [{ "id": 2316 }],
+        });
+        assert_eq!(
+            find_result(&json),
+            Some((MediaKind::Movie, "693134".to_string()))
+        );
+
+        let json = serde_json::json!({ "movie_results": [], "tv_results": [] });
+        assert_eq!(find_result(&json), None);
+    }
+
+    #[test]
+    fn test_clean_alt_title() {
+        assert_eq!(clean_alt_title("Saw 1"), "Saw");
+        assert_eq!(clean_alt_title("Vaiana 2"), "Vaiana 2");
+        assert_eq!(clean_alt_title("1917"), "1917");
+        assert_eq!(clean_alt_title("Ocean's 11"), "Ocean s 11");
+        assert_eq!(clean_alt_title("Apollo 13 1"), "Apollo 13 1");
+        assert_eq!(clean_alt_title("1"), "1");
+    }
 }
@@ -0,0 +1,507 @@
+use reqwest::header::{COOKIE, SET_COOKIE};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+const TRANSMISSION_SESSION_HEADER: &str = "X-Transmission-Session-Id";
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(CLIENT_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+    /// WebUI API v2, `url` is the WebUI address
+    Qbittorrent,
+    /// RPC, `url` is the RPC endpoint, e.g. `http://localhost:9091/transmission/rpc`
+    Transmission,
+    /// Web UI JSON-RPC, `url` is the Web UI address
+    Deluge,
+}
+
+/// A download client magnets can be sent to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadClient {
+    pub kind: ClientKind,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Category (qBittorrent) or label (Transmission, Deluge) used without `category`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Download directory used without `save_path`, the client default otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_path: Option<String>,
+}
+
+impl DownloadClient {
+    /// Add a magnet, `category` and `save_path` override the configured ones.
+    pub async fn add_magnet(
+        &self,
+        magnet: &str,
+        category: Option<&str>,
+        save_path: Option<&str>,
+    ) -> Result<(), String> {
+        let category = category.or(self.category.as_deref());
+        let save_path = save_path.or(self.save_path.as_deref());
+        let result = match self.kind {
+            ClientKind::Qbittorrent => self.qbittorrent_add(magnet, category, save_path).await,
+            ClientKind::Transmission => self.transmission_add(magnet, category, save_path).await,
+            ClientKind::Deluge => self.deluge_add(magnet, category, save_path).await,
+        };
+        result.map_err(|e| format!("{:?} at {}: {}", self.kind, self.url, e))
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.url.trim_end_matches('/'), path)
+    }
+
+    async fn qbittorrent_add(
+        &self,
+        magnet: &str,
+        category: Option<&str>,
+        save_path: Option<&str>,
+    ) -> Result<(), String> {
+        let mut add = http_client()
+            .post(self.endpoint("/api/v2/torrents/add"))
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        // Without credentials the WebUI must allow the client (e.g. localhost bypass)
+        if let Some(username) = &self.username {
+            let login = http_client()
+                .post(self.endpoint("/api/v2/auth/login"))
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                // The WebUI rejects logins without a matching Referer
+                .header("Referer", &self.url)
+                .body(form_body(&[
+                    ("username", Some(username)),
+                    ("password", self.password.as_deref()),
+                ]))
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            let cookie = session_cookie(&login, "SID");
+            let status = login.status();
+            let body = login.text().await.unwrap_or_default();
+            match cookie {
+                Some(cookie) if status.is_success() => add = add.header(COOKIE, cookie),
+                _ => return Err(format!("login failed ({}): {}", status, body.trim())),
+            }
+        }
+
+        let response = add
+            .body(form_body(&[
+                ("urls", Some(magnet)),
+                ("category", category),
+                ("savepath", save_path),
+            ]))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        // Older versions answer 200 "Fails." when the torrent is rejected
+        match status.is_success() && body.trim() != "Fails." {
+            true => Ok(()),
+            false => Err(format!("rejected ({}): {}", status, body.trim())),
+        }
+    }
+
+    async fn transmission_add(
+        &self,
+        magnet: &str,
+        label: Option<&str>,
+        save_path: Option<&str>,
+    ) -> Result<(), String> {
+        let mut arguments = json!({ "filename": magnet });
+        if let Some(save_path) = save_path {
+            arguments["download-dir"] = json!(save_path);
+        }
+        if let Some(label) = label {
+            arguments["labels"] = json!([label]);
+        }
+        let body = json!({ "method": "torrent-add", "arguments": arguments });
+
+        // The first request is answered 409 with the CSRF session id to send back
+        let mut session_id = None;
+        for _ in 0..2 {
+            let mut request = http_client().post(&self.url).json(&body);
+            if let Some(username) = &self.username {
+                request = request.basic_auth(username, self.password.as_ref());
+            }
+            if let Some(session_id) = &session_id {
+                request = request.header(TRANSMISSION_SESSION_HEADER, session_id);
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            if response.status() == StatusCode::CONFLICT {
+                session_id = response
+                    .headers()
+                    .get(TRANSMISSION_SESSION_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                continue;
+            }
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!("HTTP {}", status));
+            }
+            let reply: Value = response.json().await.map_err(|e| e.to_string())?;
+            return match reply["result"].as_str() {
+                Some("success") => Ok(()),
+                result => Err(result.unwrap_or("invalid response").to_string()),
+            };
+        }
+        Err("no session id".to_string())
+    }
+
+    async fn deluge_add(
+        &self,
+        magnet: &str,
+        label: Option<&str>,
+        save_path: Option<&str>,
+    ) -> Result<(), String> {
+        let endpoint = self.endpoint("/json");
+        let login = http_client()
+            .post(&endpoint)
+            .json(&json!({
+                "method": "auth.login",
+                "params": [self.password.as_deref().unwrap_or("")],
+                "id": 1
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let cookie = session_cookie(&login, "_session_id");
+        let reply: Value = login.json().await.map_err(|e| e.to_string())?;
+        let cookie = match cookie {
+            Some(cookie) if reply["result"] == true => cookie,
+            _ => return Err("login failed".to_string()),
+        };
+
+        // The web UI only forwards to a daemon once connected to one, use the first known host
+        if deluge_call(&endpoint, &cookie, "web.connected", json!([])).await? != true {
+            let hosts = deluge_call(&endpoint, &cookie, "web.get_hosts", json!([])).await?;
+            let host_id = hosts[0][0]
+                .as_str()
+                .ok_or("no Deluge daemon configured")?
+                .to_string();
+            deluge_call(&endpoint, &cookie, "web.connect", json!([host_id])).await?;
+        }
+
+        let mut options = json!({});
+        if let Some(save_path) = save_path {
+            options["download_location"] = json!(save_path);
+        }
+        let hash = deluge_call(
+            &endpoint,
+            &cookie,
+            "core.add_torrent_magnet",
+            json!([magnet, options]),
+        )
+        .await?;
+
+        // Labels come from a plugin, a missing one should not fail the grab
+        if let (Some(label), Some(hash)) = (label, hash.as_str()) {
+            let label = label.to_lowercase();
+            let added = deluge_call(&endpoint, &cookie, "label.add", json!([label])).await;
+            match added {
+                Err(e) if !e.contains("already exists") => {
+                    warn!("Deluge label {} not created: {}", label, e)
+                }
+                _ => {}
+            }
+            if let Err(e) = deluge_call(
+                &endpoint,
+                &cookie,
+                "label.set_torrent",
+                json!([hash, label]),
+            )
+            .await
+            {
+                warn!("Deluge label {} not set on {}: {}", label, hash, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn deluge_call(
+    endpoint: &str,
+    cookie: &str,
+    method: &str,
+    params: Value,
+) -> Result<Value, String> {
+    let response = http_client()
+        .post(endpoint)
+        .header(COOKIE, cookie)
+        .json(&json!({ "method": method, "params": params, "id": 2 }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let reply: Value = response.json().await.map_err(|e| e.to_string())?;
+    match &reply["error"] {
+        Value::Null => Ok(reply["result"].clone()),
+        error => Err(error["message"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string())),
+    }
+}
+
+/// `name=value` of the `name` cookie set by a response.
+fn session_cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .find(|cookie| cookie.starts_with(&format!("{}=", name)))
+        .map(str::to_string)
+}
+
+/// `application/x-www-form-urlencoded` body, `None` fields are left out.
+fn form_body(fields: &[(&str, Option<&str>)]) -> String {
+    fields
+        .iter()
+        .filter_map(|(key, value)| Some(format!("{}={}", key, urlencoding::encode((*value)?))))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const MAGNET: &str = "magnet:?xt=urn:btih:abc&dn=Dune";
+
+    /// Requests received by the mock server, as `path`, headers and body.
+    type Requests = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    /// HTTP server answering each request with the next canned status line, headers and body.
+    async fn mock_server(responses: Vec<(&'static str, &'static str)>) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (status, reply) in responses {
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                let (name, value) = l.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let path = head.split(' ').nth(1).unwrap_or("").to_string();
+                received.lock().unwrap().push((path, head, body));
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, requests)
+    }
+
+    fn client(kind: ClientKind, url: String) -> DownloadClient {
+        DownloadClient {
+            kind,
+            url,
+            username: Some("admin".to_string()),
+            password: Some("secret".to_string()),
+            category: Some("ygege".to_string()),
+            save_path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_qbittorrent() {
+        let (url, requests) = mock_server(vec![
+            ("200 OK\r\nSet-Cookie: SID=abc; HttpOnly; path=/", "Ok."),
+            ("200 OK", "Ok."),
+        ])
+        .await;
+        let qbittorrent = client(ClientKind::Qbittorrent, url);
+        qbittorrent
+            .add_magnet(MAGNET, None, Some("/data/films"))
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].0, "/api/v2/auth/login");
+        assert_eq!(requests[0].2, "username=admin&password=secret");
+        assert_eq!(requests[1].0, "/api/v2/torrents/add");
+        assert!(requests[1].1.contains("cookie: SID=abc"));
+        assert_eq!(
+            requests[1].2,
+            format!(
+                "urls={}&category=ygege&savepath=%2Fdata%2Ffilms",
+                urlencoding::encode(MAGNET)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transmission() {
+        let (url, requests) = mock_server(vec![
+            ("409 Conflict\r\nX-Transmission-Session-Id: tok", ""),
+            (
+                "200 OK\r\nContent-Type: application/json",
+                "{\"result\":\"success\",\"arguments\":{}}",
+            ),
+        ])
+        .await;
+        let transmission = client(
+            ClientKind::Transmission,
+            format!("{}/transmission/rpc", url),
+        );
+        transmission
+            .add_magnet(MAGNET, Some("series"), None)
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(
+            requests[1]
+                .1
+                .to_lowercase()
+                .contains("x-transmission-session-id: tok")
+        );
+        assert!(
+            requests[1]
+                .1
+                .contains("authorization: Basic YWRtaW46c2VjcmV0")
+        );
+        let body: Value = serde_json::from_str(&requests[1].2).unwrap();
+        assert_eq!(body["method"], "torrent-add");
+        assert_eq!(body["arguments"]["filename"], MAGNET);
+        assert_eq!(body["arguments"]["labels"], json!(["series"]));
+    }
+
+    #[tokio::test]
+    async fn test_deluge() {
+        let (url, requests) = mock_server(vec![
+            (
+                "200 OK\r\nSet-Cookie: _session_id=s1; path=/json",
+                "{\"result\": true, \"error\": null, \"id\": 1}",
+            ),
+            ("200 OK", "{\"result\": false, \"error\": null, \"id\": 2}"),
+            (
+                "200 OK",
+                "{\"result\": [[\"h1\", \"127.0.0.1\", 58846, \"localclient\"]], \"error\": null, \"id\": 2}",
+            ),
+            ("200 OK", "{\"result\": null, \"error\": null, \"id\": 2}"),
+            (
+                "200 OK",
+                "{\"result\": \"abc\", \"error\": null, \"id\": 2}",
+            ),
+            ("200 OK", "{\"result\": null, \"error\": null, \"id\": 2}"),
+            ("200 OK", "{\"result\": null, \"error\": null, \"id\": 2}"),
+        ])
+        .await;
+        let deluge = client(ClientKind::Deluge, url);
+        deluge
+            .add_magnet(MAGNET, None, Some("/data"))
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let bodies: Vec<Value> = requests
+            .iter()
+            .map(|(_, _, body)| serde_json::from_str(body).unwrap())
+            .collect();
+        assert!(requests.iter().all(|(path, _, _)| path == "/json"));
+        assert_eq!(bodies[0]["params"], json!(["secret"]));
+        assert!(requests[1].1.contains("cookie: _session_id=s1"));
+        let methods: Vec<&str> = bodies.iter().filter_map(|b| b["method"].as_str()).collect();
+        assert_eq!(
+            methods,
+            vec![
+                "auth.login",
+                "web.connected",
+                "web.get_hosts",
+                "web.connect",
+                "core.add_torrent_magnet",
+                "label.add",
+                "label.set_torrent"
+            ]
+        );
+        assert_eq!(bodies[3]["params"], json!(["h1"]));
+        assert_eq!(
+            bodies[4]["params"],
+            json!([MAGNET, { "download_location": "/data" }])
+        );
+        assert_eq!(bodies[6]["params"], json!(["abc", "ygege"]));
+    }
+
+    #[tokio::test]
+    async fn test_deluge_already_connected() {
+        let (url, requests) = mock_server(vec![
+            (
+                "200 OK\r\nSet-Cookie: _session_id=s1; path=/json",
+                "{\"result\": true, \"error\": null, \"id\": 1}",
+            ),
+            ("200 OK", "{\"result\": true, \"error\": null, \"id\": 2}"),
+            (
+                "200 OK",
+                "{\"result\": \"abc\", \"error\": null, \"id\": 2}",
+            ),
+        ])
+        .await;
+        let mut deluge = client(ClientKind::Deluge, url);
+        deluge.category = None;
+        deluge.add_magnet(MAGNET, None, None).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body: Value = serde_json::from_str(&requests[2].2).unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(body["method"], "core.add_torrent_magnet");
+    }
+
+    #[tokio::test]
+    async fn test_rejected() {
+        let (url, _) = mock_server(vec![("403 Forbidden", "Forbidden")]).await;
+        let mut qbittorrent = client(ClientKind::Qbittorrent, url);
+        qbittorrent.username = None;
+        let error = qbittorrent
+            .add_magnet(MAGNET, None, None)
+            .await
+            .unwrap_err();
+        assert!(error.contains("403"));
+    }
+}
@@ -0,0 +1,233 @@
+/// Season/episode markers found in a release name.
+#[derive(Debug, Clone, PartialEq)]
+enum Marker {
+    /// `S01E02`, `S01E02E03`, `S01E02-E05`, `1x02`
+    Episode { season: u32, first: u32, last: u32 },
+    /// `S01`, `S01-S03`, `Saison 2`, `Season 1 à 4`
+    Season { first: u32, last: u32 },
+    /// `Intégrale`, `Complete` without any explicit season
+    Complete,
+}
+
+/// Build the relay queries for a season/episode search, most specific first.
+pub fn episode_queries(name: &str, season: u32, episode: Option<u32>) -> Vec<String> {
+    let name = name.trim();
+    let mut queries = Vec::new();
+    if let Some(ep) = episode {
+        queries.push(format!("{} S{:02}E{:02}", name, season, ep));
+        queries.push(format!("{} {}x{:02}", name, season, ep));
+    }
+    queries.push(format!("{} S{:02}", name, season));
+    queries.push(format!("{} Saison {}", name, season));
+    queries.push(name.to_string());
+    queries
+        .into_iter()
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty())
+        .collect()
+}
+
+/// Whether a release name matches the requested season (and episode).
+/// Season packs containing the season are accepted for episode requests,
+/// names without any season marker are rejected.
+pub fn matches_episode(name: &str, season: u32, episode: Option<u32>) -> bool {
+    let markers = parse_markers(name);
+    if markers.is_empty() {
+        return false;
+    }
+
+    let has_episode_markers = markers.iter().any(|m| matches!(m, Marker::Episode { .. }));
+
+    markers.iter().any(|marker| match *marker {
+        Marker::Episode {
+            season: s,
+            first,
+            last,
+        } => s == season && episode.is_none_or(|ep| first <= ep && ep <= last),
+        // A season marker only counts as a pack when the name doesn't also list episodes
+        Marker::Season { first, last } => {
+            first <= season && season <= last && (episode.is_none() || !has_episode_markers)
+        }
+        Marker::Complete => true,
+    })
+}
+
+/// First season (and episode) found in a release name, `S02E05` → `(Some(2), Some(5))`.
+pub fn season_episode(name: &str) -> (Option<u32>, Option<u32>) {
+    parse_markers(name)
+        .into_iter()
+        .find_map(|marker| match marker {
+            Marker::Episode { season, first, .. } => Some((Some(season), Some(first))),
+            Marker::Season { first, .. } => Some((Some(first), None)),
+            Marker::Complete => None,
+        })
+        .unwrap_or((None, None))
+}
+
+fn parse_markers(name: &str) -> Vec<Marker> {
+    let lower = name.to_lowercase();
+    let tokens: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let mut markers = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+
+        if let Some((season, mut episodes)) = parse_sxxexx(token) {
+            // S01E01-E03: the range end is split into its own token
+            while let Some(next) = tokens.get(i + 1).and_then(|t| parse_prefixed(t, 'e')) {
+                episodes.push(next);
+                i += 1;
+            }
+            markers.push(Marker::Episode {
+                season,
+                first: *episodes.iter().min().unwrap(),
+                last: *episodes.iter().max().unwrap(),
+            });
+        } else if let Some((season, ep)) = parse_nxnn(token) {
+            markers.push(Marker::Episode {
+                season,
+                first: ep,
+                last: ep,
+            });
+        } else if let (Some(season), Some(ep)) = (
+            parse_prefixed(token, 's'),
+            tokens.get(i + 1).and_then(|t| parse_prefixed(t, 'e')),
+        ) {
+            // S02.E05
+            markers.push(Marker::Episode {
+                season,
+                first: ep,
+                last: ep,
+            });
+            i += 1;
+        } else if let Some(first) = parse_prefixed(token, 's') {
+            let mut last = first;
+            while let Some(next) = tokens.get(i + 1).and_then(|t| parse_prefixed(t, 's')) {
+                last = last.max(next);
+                i += 1;
+            }
+            markers.push(Marker::Season { first, last });
+        } else if let (true, Some(first)) = (
+            matches!(token, "saison" | "season" | "saisons" | "seasons"),
+            tokens.get(i + 1).and_then(|t| t.parse::<u32>().ok()),
+        ) {
+            i += 1;
+            let mut last = first;
+            // "Saison 1 à 3"
+            let range_end = tokens
+                .get(i + 1)
+                .filter(|sep| matches!(**sep, "a" | "à" | "au" | "to"))
+                .and_then(|_| tokens.get(i + 2))
+                .and_then(|t| parse_season_end(t));
+            if let Some(end) = range_end {
+                last = end.max(first);
+                i += 2;
+            } else if let Some(end) = tokens.get(i + 1).and_then(|t| parse_season_end(t)) {
+                // "Saison 1-3" splits into two numeric tokens
+                last = end.max(first);
+                i += 1;
+            }
+            markers.push(Marker::Season { first, last });
+        } else if matches!(token, "integrale" | "intégrale" | "complete" | "complète") {
+            markers.push(Marker::Complete);
+        }
+
+        i += 1;
+    }
+
+    let has_explicit = markers.iter().any(|m| !matches!(m, Marker::Complete));
+    if has_explicit {
+        markers.retain(|m| !matches!(m, Marker::Complete));
+    }
+    markers
+}
+
+/// Upper bound of a "Saison 1 à 3" range; years and resolutions are not season numbers.
+fn parse_season_end(token: &str) -> Option<u32> {
+    parse_digits(token).filter(|n| *n < 100)
+}
+
+/// `s01e02` or `s01e02e03` → (1, [2, 3])
+fn parse_sxxexx(token: &str) -> Option<(u32, Vec<u32>)> {
+    let rest = token.strip_prefix('s')?;
+    let mut parts = rest.split('e');
+    let season = parse_digits(parts.next()?)?;
+    let episodes: Vec<u32> = parts.map(parse_digits).collect::<Option<_>>()?;
+    if episodes.is_empty() {
+        return None;
+    }
+    Some((season, episodes))
+}
+
+/// `1x02` → (1, 2)
+fn parse_nxnn(token: &str) -> Option<(u32, u32)> {
+    let (season, ep) = token.split_once('x')?;
+    if season.len() > 2 || ep.len() < 2 {
+        return None;
+    }
+    Some((parse_digits(season)?, parse_digits(ep)?))
+}
+
+/// `s01` → 1 for prefix `s`, `e05` → 5 for prefix `e`
+fn parse_prefixed(token: &str, prefix: char) -> Option<u32> {
+    parse_digits(token.strip_prefix(prefix)?)
+}
+
+fn parse_digits(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() > 4 || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parse a `season`/`ep` query parameter, accepting `2`, `02` or `S02`/`E02`.
+pub fn parse_number_param(value: &str) -> Option<u32> {
+    let value = value.trim().trim_start_matches(['s', 'S', 'e', 'E']);
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_episode_match() {
+        assert!(matches_episode(
+            "Breaking.Bad.S02E05.FRENCH.720p",
+            2,
+            Some(5)
+        ));
+        assert!(!matches_episode(
+            "Breaking.Bad.S02E06.FRENCH.720p",
+            2,
+            Some(5)
+        ));
+        assert!(matches_episode("Breaking.Bad.S02E04-E06.MULTi", 2, Some(5)));
+        assert!(matches_episode("Breaking Bad 2x05 VOSTFR", 2, Some(5)));
+        assert!(!matches_episode("Breaking.Bad.S02.E06.FRENCH", 2, Some(5)));
+        assert!(!matches_episode("Breaking.Bad.S01E05.FRENCH", 2, Some(5)));
+    }
+
+    #[test]
+    fn test_season_packs() {
+        assert!(matches_episode("Breaking.Bad.S02.MULTi.1080p", 2, Some(5)));
+        assert!(matches_episode(
+            "Breaking Bad Saison 1 à 3 FRENCH",
+            2,
+            Some(5)
+        ));
+        assert!(matches_episode("Breaking.Bad.S01-S05.COMPLETE", 2, None));
+        assert!(matches_episode("Breaking Bad Intégrale MULTi", 2, None));
+        assert!(!matches_episode("Breaking.Bad.S03.MULTi.1080p", 2, None));
+        assert!(!matches_episode(
+            "Breaking Bad Saison 1 2008 FRENCH",
+            2,
+            None
+        ));
+        assert!(!matches_episode("Breaking.Bad.2008.MULTi.1080p", 2, None));
+    }
+}
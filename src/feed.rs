@@ -0,0 +1,295 @@
+use crate::categories::cat_id_to_newznab;
+use crate::parser::Torrent;
+use crate::torznab::{rfc2822_date, xml_escape};
+use sha1::{Digest, Sha1};
+use std::str::FromStr;
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FromStr for FeedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rss" => Ok(FeedFormat::Rss),
+            "atom" => Ok(FeedFormat::Atom),
+            _ => Err(format!("Invalid feed format: {}", s)),
+        }
+    }
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+/// What the enclosure of an item points to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Enclosure {
+    /// `/torrent/{id}?format=torrent`, needs `torrent_cache_urls`
+    Torrent,
+    Magnet,
+}
+
+impl FromStr for Enclosure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "torrent" => Ok(Enclosure::Torrent),
+            "magnet" => Ok(Enclosure::Magnet),
+            _ => Err(format!("Invalid enclosure: {}", s)),
+        }
+    }
+}
+
+/// A feed of torrents, newest first.
+pub struct Feed<'a> {
+    pub title: String,
+    /// Externally visible address of this server
+    pub base_url: &'a str,
+    /// URL of the feed itself
+    pub self_url: String,
+    pub enclosure: Enclosure,
+    /// Added to the `.torrent` enclosures when the feed was requested with one
+    pub apikey: Option<&'a str>,
+    pub torrents: &'a [Torrent],
+}
+
+impl Feed<'_> {
+    /// Publication date of the newest item.
+    pub fn last_modified(&self) -> Option<u64> {
+        self.torrents.iter().map(|t| t.age_stamp as u64).max()
+    }
+
+    /// Changes whenever an item, the format or the enclosure kind changes.
+    pub fn etag(&self, format: FeedFormat) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{:?}:{:?}", format, self.enclosure));
+        for torrent in self.torrents {
+            hasher.update(&torrent.id);
+            hasher.update(torrent.seed.to_le_bytes());
+        }
+        format!("\"{}\"", hex::encode(&hasher.finalize()[..10]))
+    }
+
+    pub fn to_xml(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Rss => self.rss_xml(),
+            FeedFormat::Atom => self.atom_xml(),
+        }
+    }
+
+    fn enclosure_url(&self, torrent: &Torrent) -> String {
+        match self.enclosure {
+            Enclosure::Torrent => {
+                let mut url = format!("{}/torrent/{}?format=torrent", self.base_url, torrent.id);
+                if let Some(apikey) = self.apikey {
+                    url.push_str(&format!("&apikey={}", urlencoding::encode(apikey)));
+                }
+                url
+            }
+            Enclosure::Magnet => torrent.magnet.clone(),
+        }
+    }
+
+    fn rss_xml(&self) -> String {
+        let mut xml = String::from(XML_HEADER);
+        xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">"#);
+        xml.push_str("<channel>");
+        xml.push_str(&format!("<title>{}</title>", xml_escape(&self.title)));
+        xml.push_str(&format!("<link>{}/</link>", xml_escape(self.base_url)));
+        xml.push_str("<description>ygg.gratis via Nostr</description>");
+        xml.push_str("<language>fr-FR</language>");
+        xml.push_str(&format!(
+            r#"<atom:link href="{}" rel="self" type="application/rss+xml" />"#,
+            xml_escape(&self.self_url)
+        ));
+        if let Some(last_modified) = self.last_modified() {
+            xml.push_str(&format!(
+                "<lastBuildDate>{}</lastBuildDate>",
+                rfc2822_date(last_modified)
+            ));
+        }
+
+        for torrent in self.torrents {
+            xml.push_str("<item>");
+            xml.push_str(&format!("<title>{}</title>", xml_escape(&torrent.name)));
+            xml.push_str(&format!(
+                r#"<guid isPermaLink="false">{}</guid>"#,
+                xml_escape(&torrent.id)
+            ));
+            xml.push_str(&format!("<link>{}</link>", xml_escape(&torrent.link)));
+            xml.push_str(&format!(
+                "<pubDate>{}</pubDate>",
+                rfc2822_date(torrent.age_stamp as u64)
+            ));
+            xml.push_str(&format!(
+                "<category>{}</category>",
+                cat_id_to_newznab(torrent.category_id)
+            ));
+            xml.push_str(&format!(
+                "<description>{}</description>",
+                xml_escape(&summary(torrent))
+            ));
+            xml.push_str(&format!(
+                r#"<enclosure url="{}" length="{}" type="application/x-bittorrent" />"#,
+                xml_escape(&self.enclosure_url(torrent)),
+                torrent.size
+            ));
+            xml.push_str("</item>");
+        }
+
+        xml.push_str("</channel>");
+        xml.push_str("</rss>");
+        xml
+    }
+
+    fn atom_xml(&self) -> String {
+        let mut xml = String::from(XML_HEADER);
+        xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        xml.push_str(&format!("<id>{}</id>", xml_escape(&self.self_url)));
+        xml.push_str(&format!("<title>{}</title>", xml_escape(&self.title)));
+        xml.push_str(&format!(
+            "<updated>{}</updated>",
+            rfc3339_date(self.last_modified().unwrap_or(0))
+        ));
+        xml.push_str(&format!(
+            r#"<link href="{}" rel="self" type="application/atom+xml" />"#,
+            xml_escape(&self.self_url)
+        ));
+        xml.push_str(&format!(
+            r#"<link href="{}/" rel="alternate" />"#,
+            xml_escape(self.base_url)
+        ));
+        xml.push_str("<author><name>Ygégé</name></author>");
+
+        for torrent in self.torrents {
+            xml.push_str("<entry>");
+            xml.push_str(&format!("<title>{}</title>", xml_escape(&torrent.name)));
+            // Atom IDs must be IRIs, the event id is wrapped in a URN
+            xml.push_str(&format!("<id>urn:nostr:{}</id>", xml_escape(&torrent.id)));
+            xml.push_str(&format!(
+                "<updated>{}</updated>",
+                rfc3339_date(torrent.age_stamp as u64)
+            ));
+            xml.push_str(&format!(
+                r#"<link href="{}" rel="alternate" />"#,
+                xml_escape(&torrent.link)
+            ));
+            xml.push_str(&format!(
+                r#"<link href="{}" rel="enclosure" type="application/x-bittorrent" length="{}" />"#,
+                xml_escape(&self.enclosure_url(torrent)),
+                torrent.size
+            ));
+            xml.push_str(&format!(
+                r#"<category term="{}" />"#,
+                cat_id_to_newznab(torrent.category_id)
+            ));
+            xml.push_str(&format!(
+                "<summary>{}</summary>",
+                xml_escape(&summary(torrent))
+            ));
+            xml.push_str("</entry>");
+        }
+
+        xml.push_str("</feed>");
+        xml
+    }
+}
+
+/// One-line size and peers description, for feed items and notifications.
+pub(crate) fn summary(torrent: &Torrent) -> String {
+    format!(
+        "Size: {:.2} GiB, seeders: {}, leechers: {}, completed: {}",
+        torrent.size as f64 / (1u64 << 30) as f64,
+        torrent.seed,
+        torrent.leech,
+        torrent.completed
+    )
+}
+
+/// Format a unix timestamp as an RFC 3339 date (e.g. `1994-11-06T08:49:37Z`).
+pub fn rfc3339_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+    // Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent() -> Torrent {
+        Torrent {
+            id: "e1d2".to_string(),
+            category_id: 2183,
+            name: "Dune & Co 2021".to_string(),
+            age_stamp: 1_700_000_000,
+            size: 1 << 30,
+            seed: 5,
+            magnet: "magnet:?xt=urn:btih:abc&dn=Dune".to_string(),
+            link: "https://ygg.gratis/engine/torrent?id=e1d2".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rss_and_atom() {
+        let torrents = vec![torrent()];
+        let mut feed = Feed {
+            title: "Ygégé".to_string(),
+            base_url: "http://localhost:8715",
+            self_url: "http://localhost:8715/rss".to_string(),
+            enclosure: Enclosure::Torrent,
+            apikey: None,
+            torrents: &torrents,
+        };
+        let rss = feed.to_xml(FeedFormat::Rss);
+        assert!(rss.contains(r#"<guid isPermaLink="false">e1d2</guid>"#));
+        assert!(rss.contains("<title>Dune &amp; Co 2021</title>"));
+        assert!(rss.contains(
+            r#"<enclosure url="http://localhost:8715/torrent/e1d2?format=torrent" length="1073741824" type="application/x-bittorrent" />"#
+        ));
+        assert!(rss.contains("<pubDate>Tue, 14 Nov 2023 22:13:20 GMT</pubDate>"));
+
+        let etag = feed.etag(FeedFormat::Rss);
+        assert_ne!(etag, feed.etag(FeedFormat::Atom));
+        feed.enclosure = Enclosure::Magnet;
+        assert_ne!(etag, feed.etag(FeedFormat::Rss));
+
+        let atom = feed.to_xml(FeedFormat::Atom);
+        assert!(atom.contains("<id>urn:nostr:e1d2</id>"));
+        assert!(atom.contains("<updated>2023-11-14T22:13:20Z</updated>"));
+        assert!(atom.contains(r#"href="magnet:?xt=urn:btih:abc&amp;dn=Dune" rel="enclosure""#));
+    }
+}
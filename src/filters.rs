@@ -0,0 +1,289 @@
+use crate::parser::Torrent;
+use crate::release::canonical;
+use qstring::QString;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Result filters read from the `/search` query string. Every filter that is set must
+/// match; a comma-separated list matches any of its values.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    /// Size bounds in bytes, both inclusive
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub min_seed: Option<usize>,
+    /// Publication date bounds as unix timestamps, both inclusive. `max_age` is folded
+    /// into `after`.
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+    /// Words that must all appear in the name
+    pub require_words: Vec<String>,
+    pub resolution: Vec<String>,
+    pub source: Vec<String>,
+    pub codec: Vec<String>,
+    pub audio: Vec<String>,
+    pub language: Vec<String>,
+    pub hdr: Vec<String>,
+    pub group: Vec<String>,
+    pub year: Vec<u32>,
+}
+
+impl SearchFilter {
+    /// Query string parameters read by `from_query`.
+    pub const PARAMS: &[&str] = &[
+        "min_size",
+        "max_size",
+        "min_seed",
+        "max_age",
+        "after",
+        "before",
+        "require_words",
+        "resolution",
+        "source",
+        "codec",
+        "audio",
+        "language",
+        "hdr",
+        "group",
+        "year",
+    ];
+
+    pub fn from_query(qs: &QString) -> Self {
+        let list = |key: &str| -> Vec<String> {
+            qs.get(key)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(normalize)
+                        .filter(|v| !v.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let max_age_start = qs
+            .get("max_age")
+            .and_then(parse_duration)
+            .map(|age| now().saturating_sub(age));
+        let after = qs.get("after").and_then(parse_date);
+
+        SearchFilter {
+            min_size: qs.get("min_size").and_then(parse_size),
+            max_size: qs.get("max_size").and_then(parse_size),
+            min_seed: qs.get("min_seed").and_then(|s| s.trim().parse().ok()),
+            after: after.max(max_age_start),
+            before: qs.get("before").and_then(parse_date),
+            require_words: qs
+                .get("require_words")
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|w| w.trim().to_lowercase())
+                        .filter(|w| !w.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            resolution: list("resolution"),
+            source: list("source"),
+            codec: list("codec"),
+            audio: list("audio"),
+            language: list("language"),
+            hdr: list("hdr"),
+            group: list("group"),
+            year: list("year").iter().filter_map(|y| y.parse().ok()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.min_seed.is_none()
+            && self.after.is_none()
+            && self.before.is_none()
+            && self.require_words.is_empty()
+            && self.resolution.is_empty()
+            && self.source.is_empty()
+            && self.codec.is_empty()
+            && self.audio.is_empty()
+            && self.language.is_empty()
+            && self.hdr.is_empty()
+            && self.group.is_empty()
+            && self.year.is_empty()
+    }
+
+    pub fn matches(&self, torrent: &Torrent) -> bool {
+        let date = torrent.age_stamp as u64;
+        if self.min_size.is_some_and(|min| torrent.size < min)
+            || self.max_size.is_some_and(|max| torrent.size > max)
+            || self.min_seed.is_some_and(|min| torrent.seed < min)
+            || self.after.is_some_and(|after| date < after)
+            || self.before.is_some_and(|before| date > before)
+        {
+            return false;
+        }
+        if !self.require_words.is_empty() {
+            let name = torrent.name.to_lowercase();
+            if !self.require_words.iter().all(|w| name.contains(w.as_str())) {
+                return false;
+            }
+        }
+
+        let release = &torrent.release;
+        let one = |wanted: &[String], value: &Option<String>| {
+            wanted.is_empty()
+                || value
+                    .as_ref()
+                    .is_some_and(|v| wanted.iter().any(|w| w.eq_ignore_ascii_case(v)))
+        };
+        let any = |wanted: &[String], values: &[String]| {
+            wanted.is_empty()
+                || values
+                    .iter()
+                    .any(|v| wanted.iter().any(|w| w.eq_ignore_ascii_case(v)))
+        };
+
+        one(&self.resolution, &release.resolution)
+            && one(&self.source, &release.source)
+            && one(&self.codec, &release.codec)
+            && any(&self.audio, &release.audio)
+            && any(&self.language, &release.languages)
+            && any(&self.hdr, &release.hdr)
+            && one(&self.group, &release.group)
+            && (self.year.is_empty() || release.year.is_some_and(|y| self.year.contains(&y)))
+    }
+
+    pub fn apply(&self, torrents: &mut Vec<Torrent>) {
+        if !self.is_empty() {
+            torrents.retain(|t| self.matches(t));
+        }
+    }
+}
+
+/// Map a filter value to the canonical tag, so `x265` matches `H265` and `4k` matches `2160p`.
+pub(crate) fn normalize(value: &str) -> String {
+    let value = value.trim();
+    let lower = value.to_lowercase().replace(['-', '.', ' '], "");
+    match canonical(&lower) {
+        Some((_, canonical)) => canonical.to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// `1500000`, `700MB`, `1.5 GiB`. Decimal and binary units are both read as powers of 1024,
+/// like the sizes displayed by YGG.
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// `3600`, `90m`, `12h`, `7d`, `2w` -> seconds
+fn parse_duration(value: &str) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+    Some(number * multiplier)
+}
+
+/// A unix timestamp or a `YYYY-MM-DD` date (midnight UTC).
+fn parse_date(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse() {
+        return Some(timestamp);
+    }
+    let mut parts = value.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days since the epoch, from Howard Hinnant's `days_from_civil`
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u64::try_from(days * 86400).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(name: &str) -> Torrent {
+        Torrent {
+            age_stamp: 1_700_000_000,
+            size: 2 << 30,
+            seed: 10,
+            ..Torrent::named(name)
+        }
+    }
+
+    #[test]
+    fn test_release_filters() {
+        let qs = QString::from("resolution=4k,1080p&codec=x265&language=multi");
+        let filter = SearchFilter::from_query(&qs);
+        assert_eq!(filter.resolution, vec!["2160p", "1080p"]);
+
+        assert!(filter.matches(&torrent("Dune.2021.MULTi.2160p.WEB-DL.x265-GRP")));
+        assert!(!filter.matches(&torrent("Dune.2021.MULTi.720p.WEB-DL.x265-GRP")));
+        assert!(!filter.matches(&torrent("Dune.2021.VOSTFR.1080p.WEB-DL.H265-GRP")));
+        assert!(SearchFilter::from_query(&QString::from("q=dune")).is_empty());
+    }
+
+    #[test]
+    fn test_torrent_filters() {
+        let filter = SearchFilter::from_query(&QString::from(
+            "min_size=1GB&max_size=4.5GiB&min_seed=5&after=2023-11-01&before=1800000000&require_words=dune,MULTi",
+        ));
+        assert_eq!(filter.min_size, Some(1 << 30));
+        assert_eq!(filter.after, Some(1_698_796_800));
+        assert!(filter.matches(&torrent("Dune.2021.MULTi.1080p.WEB-DL.x265-GRP")));
+        assert!(!filter.matches(&torrent("Dune.2021.VOSTFR.1080p.WEB-DL.x265-GRP")));
+
+        let mut small = torrent("Dune.2021.MULTi.720p.WEB-DL.x265-GRP");
+        small.size = 700 << 20;
+        assert!(!filter.matches(&small));
+        let mut unseeded = torrent("Dune.2021.MULTi.1080p.WEB-DL.x265-GRP");
+        unseeded.seed = 1;
+        assert!(!filter.matches(&unseeded));
+
+        let recent = SearchFilter::from_query(&QString::from("max_age=7d"));
+        assert!(!recent.matches(&torrent("Dune.2021.MULTi.1080p.WEB-DL.x265-GRP")));
+        assert_eq!(parse_duration("90m"), Some(5400));
+        assert_eq!(parse_size("12kb"), Some(12 << 10));
+        assert_eq!(parse_size("12 parsecs"), None);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::dbs::MediaKind;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Title types kept from `title.basics`, episodes are resolved through their show.
+const KEPT_TYPES: &[&str] = &[
+    "movie",
+    "tvMovie",
+    "video",
+    "tvSeries",
+    "tvMiniSeries",
+    "tvSpecial",
+];
+
+/// A title of the imported IMDb dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImdbTitle {
+    pub kind: MediaKind,
+    pub year: Option<u32>,
+    pub primary_title: String,
+    pub original_title: String,
+    pub akas: Vec<Aka>,
+}
+
+/// A regional title from `title.akas`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aka {
+    pub region: String,
+    /// Whether IMDb displays it as the title in that region
+    pub display: bool,
+    pub title: String,
+}
+
+/// Titles imported from the IMDb datasets, one per line sorted by numeric ID:
+/// `id, kind, year, primary title, original title` followed by `region, display, title`
+/// triples. Lookups binary search the file, nothing is loaded in memory.
+#[derive(Debug, Clone)]
+pub struct ImdbDataset {
+    path: PathBuf,
+}
+
+impl ImdbDataset {
+    /// Open an imported dataset, `None` if it does not exist.
+    pub fn open(path: &str) -> Option<Self> {
+        let path = PathBuf::from(path);
+        path.is_file().then_some(ImdbDataset { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, imdb_id: &str) -> Result<Option<ImdbTitle>, Box<dyn std::error::Error>> {
+        let Some(id) = parse_tconst(imdb_id) else {
+            return Err(format!("Invalid IMDb ID {}", imdb_id).into());
+        };
+        let mut file = BufReader::new(std::fs::File::open(&self.path)?);
+        let (mut lo, mut hi) = (0, file.get_ref().metadata()?.len());
+
+        // `lo` is always the start of a line, the target line starts in `lo..hi`
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let Some((start, line)) = line_at(&mut file, mid)? else {
+                hi = mid;
+                continue;
+            };
+            if start >= hi {
+                hi = mid;
+                continue;
+            }
+            let line_id = line.split('\t').next().and_then(|i| i.parse::<u64>().ok());
+            match line_id.map(|line_id| line_id.cmp(&id)) {
+                Some(std::cmp::Ordering::Equal) => return Ok(parse_line(&line)),
+                Some(std::cmp::Ordering::Less) => lo = start + line.len() as u64 + 1,
+                _ => hi = mid,
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The first complete line starting at or after `pos`, with its offset.
+fn line_at(
+    file: &mut BufReader<std::fs::File>,
+    pos: u64,
+) -> std::io::Result<Option<(u64, String)>> {
+    let mut start = pos;
+    if pos > 0 {
+        file.seek(SeekFrom::Start(pos - 1))?;
+        let mut skipped = Vec::new();
+        file.read_until(b'\n', &mut skipped)?;
+        start = pos - 1 + skipped.len() as u64;
+    } else {
+        file.seek(SeekFrom::Start(0))?;
+    }
+    let mut line = String::new();
+    if file.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(Some((start, line)))
+}
+
+fn parse_line(line: &str) -> Option<ImdbTitle> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 5 {
+        return None;
+    }
+    let kind = match fields[1] {
+        "tv" => MediaKind::Tv,
+        _ => MediaKind::Movie,
+    };
+    let akas = fields[5..]
+        .chunks_exact(3)
+        .map(|aka| Aka {
+            region: aka[0].to_string(),
+            display: aka[1] == "d",
+            title: aka[2].to_string(),
+        })
+        .collect();
+    Some(ImdbTitle {
+        kind,
+        year: fields[2].parse().ok(),
+        primary_title: fields[3].to_string(),
+        original_title: fields[4].to_string(),
+        akas,
+    })
+}
+
+/// `tt0111161` (or `0111161`) -> 111161
+fn parse_tconst(tconst: &str) -> Option<u64> {
+    let tconst = tconst.trim();
+    tconst.strip_prefix("tt").unwrap_or(tconst).parse().ok()
+}
+
+/// `\N` is the null value of the IMDb datasets.
+fn non_null(value: &str) -> Option<&str> {
+    (value != "\\N" && !value.is_empty()).then_some(value)
+}
+
+/// Open a dataset file, decompressing it if it ends with `.gz`.
+fn open_tsv(path: &Path) -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let reader: Box<dyn Read> = match path.extension().is_some_and(|e| e == "gz") {
+        true => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        false => Box::new(file),
+    };
+    Ok(Box::new(BufReader::with_capacity(1 << 20, reader)))
+}
+
+/// `title.basics.tsv.gz` in `dir`, or its uncompressed version.
+fn dataset_file(dir: &Path, name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    [format!("{}.tsv.gz", name), format!("{}.tsv", name)]
+        .into_iter()
+        .map(|file| dir.join(file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("{}.tsv(.gz) not found in {}", name, dir.display()).into())
+}
+
+/// Import `title.basics` and `title.akas` from `dir` into `output`, keeping the regional
+/// titles of `regions` only. Returns the number of imported titles.
+pub fn import_dir(
+    dir: &Path,
+    regions: &[String],
+    output: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let basics = open_tsv(&dataset_file(dir, "title.basics")?)?;
+    let akas = open_tsv(&dataset_file(dir, "title.akas")?)?;
+
+    // Write next to the target and rename, so a running instance never reads a partial file
+    let tmp_path = format!("{}.tmp", output);
+    let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+    let count = import(basics, akas, regions, &mut out)?;
+    out.flush()?;
+    drop(out);
+    std::fs::rename(&tmp_path, output)?;
+    Ok(count)
+}
+
+/// Merge the two datasets into the sorted line format read by [`ImdbDataset`].
+/// The kept titles are held in memory until written out: about 1.5 million rows of
+/// `title.basics`, plan for 1 GB. The lookups afterwards load nothing.
+pub fn import(
+    basics: impl BufRead,
+    akas: impl BufRead,
+    regions: &[String],
+    out: &mut impl Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut titles: BTreeMap<u64, ImdbTitle> = BTreeMap::new();
+
+    // tconst titleType primaryTitle originalTitle isAdult startYear ...
+    for line in basics.lines().skip(1) {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 6 || !KEPT_TYPES.contains(&fields[1]) {
+            continue;
+        }
+        let (Some(id), Some(primary_title)) = (parse_tconst(fields[0]), non_null(fields[2])) else {
+            continue;
+        };
+        let kind = match fields[1] {
+            "tvSeries" | "tvMiniSeries" => MediaKind::Tv,
+            _ => MediaKind::Movie,
+        };
+        titles.insert(
+            id,
+            ImdbTitle {
+                kind,
+                year: non_null(fields[5]).and_then(|y| y.parse().ok()),
+                primary_title: primary_title.to_string(),
+                original_title: non_null(fields[3]).unwrap_or(primary_title).to_string(),
+                akas: Vec::new(),
+            },
+        );
+    }
+
+    // titleId ordering title region language types attributes isOriginalTitle
+    for line in akas.lines().skip(1) {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 6 {
+            continue;
+        }
+        let (Some(id), Some(title), Some(region)) = (
+            parse_tconst(fields[0]),
+            non_null(fields[2]),
+            non_null(fields[3]),
+        ) else {
+            continue;
+        };
+        if fields[5] == "working" || !regions.iter().any(|r| r.eq_ignore_ascii_case(region)) {
+            continue;
+        }
+        let Some(entry) = titles.get_mut(&id) else {
+            continue;
+        };
+        if !entry
+            .akas
+            .iter()
+            .any(|a| a.region == region && a.title == title)
+        {
+            entry.akas.push(Aka {
+                region: region.to_string(),
+                display: fields[5] == "imdbDisplay",
+                title: title.to_string(),
+            });
+        }
+    }
+
+    for (id, title) in &titles {
+        let kind = match title.kind {
+            MediaKind::Tv => "tv",
+            MediaKind::Movie => "movie",
+        };
+        let year = title.year.map(|y| y.to_string()).unwrap_or_default();
+        write!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            id, kind, year, title.primary_title, title.original_title
+        )?;
+        for aka in &title.akas {
+            let display = if aka.display { "d" } else { "a" };
+            write!(out, "\t{}\t{}\t{}", aka.region, display, aka.title)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(titles.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASICS: &str = "tconst\ttitleType\tprimaryTitle\toriginalTitle\tisAdult\tstartYear\tendYear\truntimeMinutes\tgenres
+tt0000009\tmovie\tMiss Jerry\tMiss Jerry\t0\t1894\t\\N\t45\tRomance
+tt0111161\tmovie\tThe Shawshank Redemption\tThe Shawshank Redemption\t0\t1994\t\\N\t142\tDrama
+tt0959621\ttvEpisode\tPilot\tPilot\t0\t2008\t\\N\t58\tCrime
+tt0903747\ttvSeries\tBreaking Bad\tBreaking Bad\t0\t2008\t2013\t49\tCrime
+tt13622776\tmovie\tAhsoka\tAhsoka\t0\t\\N\t\\N\t\\N\tAction
+";
+
+    const AKAS: &str =
+        "titleId\tordering\ttitle\tregion\tlanguage\ttypes\tattributes\tisOriginalTitle
+tt0111161\t1\tLes évadés\tFR\t\\N\timdbDisplay\t\\N\t0
+tt0111161\t2\tRita Hayworth and Shawshank Redemption\tUS\t\\N\tworking\t\\N\t0
+tt0111161\t3\tÀ l'ombre de Shawshank\tCA\tfr\timdbDisplay\t\\N\t0
+tt0111161\t4\tDie Verurteilten\tDE\t\\N\timdbDisplay\t\\N\t0
+tt0903747\t1\tBreaking Bad\tFR\t\\N\t\\N\t\\N\t0
+";
+
+    #[test]
+    fn test_import_and_lookup() {
+        let regions = vec!["FR".to_string(), "CA".to_string(), "US".to_string()];
+        let mut out = Vec::new();
+        let count = import(BASICS.as_bytes(), AKAS.as_bytes(), &regions, &mut out).unwrap();
+        assert_eq!(count, 4);
+
+        let path = std::env::temp_dir().join(format!("ygege_imdb_{}.tsv", std::process::id()));
+        std::fs::write(&path, &out).unwrap();
+        let dataset = ImdbDataset::open(path.to_str().unwrap()).unwrap();
+
+        let shawshank = dataset.get("tt0111161").unwrap().unwrap();
+        assert_eq!(shawshank.year, Some(1994));
+        let akas: Vec<&str> = shawshank.akas.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(akas, vec!["Les évadés", "À l'ombre de Shawshank"]);
+
+        assert_eq!(
+            dataset.get("tt0903747").unwrap().unwrap().kind,
+            MediaKind::Tv
+        );
+        assert_eq!(dataset.get("tt13622776").unwrap().unwrap().year, None);
+        assert!(dataset.get("tt0000009").unwrap().is_some());
+        assert!(dataset.get("tt0959621").unwrap().is_none());
+        assert!(dataset.get("tt9999999").unwrap().is_none());
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,438 @@
+use crate::config::data_path;
+use crate::nostr::{ALLOWED_PUBKEY, NostrClient, SearchBatch, parse_nip35_event};
+use crate::parser::{ExternalId, Torrent};
+use crate::search::tokenize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+/// Events requested per backfill page.
+const BACKFILL_PAGE_SIZE: usize = 500;
+/// Delay before retrying a failed backfill page.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+static LOCAL_INDEX: OnceLock<Arc<LocalIndex>> = OnceLock::new();
+
+/// The local index, when enabled in the configuration.
+pub fn get_local_index() -> Option<&'static Arc<LocalIndex>> {
+    LOCAL_INDEX.get()
+}
+
+struct Entry {
+    torrent: Torrent,
+    created_at: u64,
+    /// Lowercased `#t` tag values, used for category filters
+    tags: Vec<String>,
+    tokens: Vec<String>,
+}
+
+/// Local copy of every kind-2003 event published by the Ygg pubkey.
+/// Verified events are appended as JSON lines to a single file and loaded back at startup.
+pub struct LocalIndex {
+    entries: RwLock<HashMap<String, Entry>>,
+    file: Mutex<std::fs::File>,
+    ready: AtomicBool,
+}
+
+impl LocalIndex {
+    /// Open (or create) the index file and load every stored event.
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut entries = HashMap::new();
+        if std::path::Path::new(path).exists() {
+            let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+            for line in reader.lines() {
+                let line = line?;
+                let Ok(event) = serde_json::from_str::<Value>(&line) else {
+                    warn!("Skipping corrupted line in local index {}", path);
+                    continue;
+                };
+                if let Some(entry) = to_entry(&event) {
+                    entries.insert(entry.torrent.id.clone(), entry);
+                }
+            }
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(LocalIndex {
+            entries: RwLock::new(entries),
+            file: Mutex::new(file),
+            ready: AtomicBool::new(false),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    /// Whether the history backfill has completed since startup.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Add verified events and append the new ones to the index file.
+    /// Returns how many were not indexed yet.
+    pub async fn add(self: &Arc<Self>, events: &[Value]) -> usize {
+        let new_events: Vec<String> = events
+            .iter()
+            .filter(|e| self.insert(e))
+            .map(|e| e.to_string())
+            .collect();
+        let added = new_events.len();
+        if added == 0 {
+            return 0;
+        }
+
+        let index = self.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            let mut file = index.file.lock().unwrap();
+            new_events
+                .iter()
+                .try_for_each(|line| writeln!(file, "{}", line))
+        })
+        .await;
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to persist events to local index: {}", e),
+            Err(e) => error!("Failed to persist events to local index: {}", e),
+        }
+        added
+    }
+
+    /// Add a verified event in memory. Returns false if it was already indexed or is not a torrent.
+    fn insert(&self, event: &Value) -> bool {
+        let Some(entry) = to_entry(event) else {
+            return false;
+        };
+
+        let mut entries = self.entries.write().unwrap();
+        if entries.contains_key(&entry.torrent.id) {
+            return false;
+        }
+        entries.insert(entry.torrent.id.clone(), entry);
+        true
+    }
+
+    /// `created_at` of the newest indexed event.
+    pub fn newest(&self) -> Option<u64> {
+        self.bounds().map(|(_, newest)| newest)
+    }
+
+    /// `(oldest, newest)` `created_at` of the indexed events.
+    fn bounds(&self) -> Option<(u64, u64)> {
+        let entries = self.entries.read().unwrap();
+        let oldest = entries.values().map(|e| e.created_at).min()?;
+        let newest = entries.values().map(|e| e.created_at).max()?;
+        Some((oldest, newest))
+    }
+
+    /// Full-text search over the indexed names, mirroring the relay query semantics:
+    /// every query token must appear in the name, a quoted query must appear as a phrase.
+    /// `since`/`until` bound the event `created_at`, both inclusive.
+    pub fn search(
+        &self,
+        query: &str,
+        tag_filter: Option<&str>,
+        limit: usize,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> SearchBatch {
+        let query = query.trim();
+        let phrase = query.len() > 1 && query.starts_with('"') && query.ends_with('"');
+        let query_tokens = tokenize(query);
+        let tag_filter = tag_filter.map(|t| t.to_lowercase());
+
+        let entries = self.entries.read().unwrap();
+        let mut matches: Vec<&Entry> = entries
+            .values()
+            .filter(|e| since.is_none_or(|s| e.created_at >= s))
+            .filter(|e| until.is_none_or(|u| e.created_at <= u))
+            .filter(|e| {
+                tag_filter
+                    .as_ref()
+                    .is_none_or(|tag| e.tags.iter().any(|t| t == tag))
+            })
+            .filter(|e| match phrase {
+                true => {
+                    query_tokens.is_empty()
+                        || e.tokens
+                            .windows(query_tokens.len())
+                            .any(|w| w == query_tokens.as_slice())
+                }
+                false => query_tokens.iter().all(|t| e.tokens.contains(t)),
+            })
+            .collect();
+
+        matches.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+        matches.truncate(limit);
+        SearchBatch {
+            event_count: matches.len(),
+            oldest_created_at: matches.last().map(|e| e.created_at),
+            created_at: matches
+                .iter()
+                .map(|e| (e.torrent.id.clone(), e.created_at))
+                .collect(),
+            torrents: matches.into_iter().map(|e| e.torrent.clone()).collect(),
+        }
+    }
+
+    /// Torrents tagged with any of `external_ids`, newest first.
+    pub fn search_external_ids(
+        &self,
+        external_ids: &[ExternalId],
+        tag_filter: Option<&str>,
+        limit: usize,
+    ) -> Vec<Torrent> {
+        let tag_filter = tag_filter.map(|t| t.to_lowercase());
+        let entries = self.entries.read().unwrap();
+        let mut matches: Vec<&Entry> = entries
+            .values()
+            .filter(|e| {
+                e.torrent
+                    .external_ids
+                    .iter()
+                    .any(|id| external_ids.contains(id))
+            })
+            .filter(|e| {
+                tag_filter
+                    .as_ref()
+                    .is_none_or(|tag| e.tags.iter().any(|t| t == tag))
+            })
+            .collect();
+
+        matches.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|e| e.torrent.clone())
+            .collect()
+    }
+}
+
+fn to_entry(event: &Value) -> Option<Entry> {
+    let torrent = parse_nip35_event(event.clone())?;
+    let tags = event["tags"]
+        .as_array()?
+        .iter()
+        .filter_map(|t| {
+            let arr = t.as_array()?;
+            if arr.first()?.as_str()? == "t" {
+                arr.get(1)?.as_str().map(|s| s.to_lowercase())
+            } else {
+                None
+            }
+        })
+        .collect();
+    Some(Entry {
+        tokens: tokenize(&torrent.name),
+        created_at: event["created_at"].as_u64().unwrap_or(0),
+        torrent,
+        tags,
+    })
+}
+
+/// Open the local index and start backfilling it from the relays in the background.
+/// New events come from the live stream, see [`crate::stream::init_live_stream`].
+pub async fn init_local_index(
+    path: &str,
+    nostr: Arc<NostrClient>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // A relative path is next to `config.json`
+    let path = data_path(path).to_string_lossy().to_string();
+    let owned_path = path.clone();
+    let index = tokio::task::spawn_blocking(move || {
+        LocalIndex::open(&owned_path).map_err(|e| e.to_string())
+    })
+    .await??;
+    let index = Arc::new(index);
+    info!("Local index loaded: {} torrents from {}", index.len(), path);
+    LOCAL_INDEX
+        .set(index.clone())
+        .map_err(|_| "Local index already initialized")?;
+
+    tokio::spawn(async move { backfill(index, nostr).await });
+    Ok(())
+}
+
+/// Walk the history backwards with `until` until the relay returns nothing new.
+async fn backfill(index: Arc<LocalIndex>, nostr: Arc<NostrClient>) {
+    let mut until = index.bounds().map(|(oldest, _)| oldest);
+    let mut added_total = 0;
+
+    loop {
+        let mut filter = json!({
+            "kinds": [2003],
+            "authors": [ALLOWED_PUBKEY],
+            "limit": BACKFILL_PAGE_SIZE
+        });
+        if let Some(until) = until {
+            filter["until"] = json!(until);
+        }
+
+        let events = match nostr.query(filter).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Local index backfill failed, retrying: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let added = index.add(&events).await;
+        added_total += added;
+        let oldest = events.iter().filter_map(|e| e["created_at"].as_u64()).min();
+        debug!(
+            "Local index backfill page: {} events, {} new (until {:?})",
+            events.len(),
+            added,
+            until
+        );
+
+        match oldest {
+            // `until` is inclusive, so a page made only of known events means we are done
+            Some(oldest) if added > 0 => until = Some(oldest),
+            _ => break,
+        }
+    }
+
+    index.ready.store(true, Ordering::Relaxed);
+    info!(
+        "Local index backfill complete: {} new torrents, {} total",
+        added_total,
+        index.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, title: &str, created_at: u64, tag: &str, external_id: &str) -> Value {
+        json!({
+            "id": id,
+            "kind": 2003,
+            "pubkey": ALLOWED_PUBKEY,
+            "created_at": created_at,
+            "content": "",
+            "tags": [
+                ["title", title],
+                ["x", "0123456789abcdef0123456789abcdef01234567"],
+                ["t", tag],
+                ["i", external_id]
+            ]
+        })
+    }
+
+    fn ids(torrents: &[Torrent]) -> Vec<&str> {
+        torrents.iter().map(|t| t.id.as_str()).collect()
+    }
+
+    fn open(name: &str) -> (std::path::PathBuf, Arc<LocalIndex>) {
+        crate::trackers::init_test_trackers();
+        let path =
+            std::env::temp_dir().join(format!("ygege_index_{}_{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let index = Arc::new(LocalIndex::open(path.to_str().unwrap()).unwrap());
+        (path, index)
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let (path, index) = open("search");
+        index
+            .add(&[
+                event(
+                    "a",
+                    "Dune.Part.Two.2024.1080p",
+                    30,
+                    "Film",
+                    "imdb:tt15239678",
+                ),
+                event("b", "Dune.2021.MULTi.2160p", 20, "film", "imdb:tt1160419"),
+                event("c", "Two.Dune.Walkers.2024", 10, "serie", "imdb:tt0000001"),
+            ])
+            .await;
+
+        let batch = index.search("dune", None, 10, None, None);
+        assert_eq!(ids(&batch.torrents), vec!["a", "b", "c"]);
+        assert_eq!(batch.oldest_created_at, Some(10));
+        assert_eq!(batch.created_at["b"], 20);
+
+        assert_eq!(
+            ids(&index.search("\"dune part\"", None, 10, None, None).torrents),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(&index.search("two dune", None, 10, None, None).torrents),
+            vec!["a", "c"]
+        );
+        assert_eq!(
+            ids(&index.search("dune", Some("FILM"), 10, None, None).torrents),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            ids(&index.search("dune", None, 10, Some(10), Some(20)).torrents),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            ids(&index.search("dune", None, 1, None, None).torrents),
+            vec!["a"]
+        );
+
+        let wanted = [ExternalId::imdb("tt1160419"), ExternalId::imdb("0000001")];
+        assert_eq!(
+            ids(&index.search_external_ids(&wanted, None, 10)),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            ids(&index.search_external_ids(&wanted, Some("serie"), 10)),
+            vec!["c"]
+        );
+        assert_eq!(ids(&index.search_external_ids(&wanted, None, 1)), vec!["b"]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_load_append_and_dedupe() {
+        let (path, index) = open("roundtrip");
+        let first = event("a", "Dune.2021", 10, "film", "imdb:tt1160419");
+        assert_eq!(index.add(&[first.clone(), first.clone()]).await, 1);
+        assert_eq!(
+            index
+                .add(&[
+                    first,
+                    event("b", "Arrival.2016", 20, "film", "imdb:tt2543164")
+                ])
+                .await,
+            1
+        );
+        // Not a torrent, neither indexed nor written
+        assert_eq!(index.add(&[json!({"id": "c", "tags": []})]).await, 0);
+        drop(index);
+
+        let mut contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        // A corrupted line and a duplicate are skipped on load
+        contents.push_str("{not json\n");
+        contents.push_str(&format!(
+            "{}\n",
+            event("a", "Dune.2021", 10, "film", "imdb:tt1160419")
+        ));
+        std::fs::write(&path, contents).unwrap();
+
+        let index = LocalIndex::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.bounds(), Some((10, 20)));
+        assert_eq!(
+            ids(&index.search("dune", None, 10, None, None).torrents),
+            vec!["a"]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+}
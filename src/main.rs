@@ -1,15 +1,38 @@
+mod auth;
+mod ban_words;
+mod bencode;
+mod cache;
 mod categories;
 mod config;
 mod dbs;
+mod download_client;
+mod episodes;
+mod feed;
+mod filters;
+mod imdb_dataset;
+mod index;
 mod nostr;
 mod parser;
+mod ranking;
 mod rate_limiter;
+mod relay_pool;
+mod release;
 pub mod rest;
 mod search;
-
+mod stream;
+mod tmdb_cache;
+mod torrent_file;
+mod torznab;
+mod trackers;
+mod watch;
+
+use crate::cache::init_search_cache;
 use crate::categories::{CATEGORIES_CACHE, init_categories};
 use crate::config::load_config;
+use crate::index::init_local_index;
 use crate::nostr::{NostrClient, rank_relays};
+use crate::tmdb_cache::init_tmdb_cache;
+use crate::trackers::init_trackers;
 use actix_web::{App, HttpServer, web};
 
 extern crate pretty_env_logger;
@@ -64,6 +87,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         VERSION, BUILD_COMMIT, BUILD_BRANCH, BUILD_DATE
     );
 
+    if args.len() > 1 && args[1] == "--import-imdb" {
+        let Some(dir) = args.get(2) else {
+            eprintln!("Usage: ygege --import-imdb <directory with title.basics and title.akas>");
+            std::process::exit(1);
+        };
+        info!("Importing IMDb datasets from {}...", dir);
+        match imdb_dataset::import_dir(
+            std::path::Path::new(dir),
+            &config.tmdb_regions,
+            &config.imdb_dataset_path,
+        ) {
+            Ok(count) => info!(
+                "Imported {} titles into {}",
+                count, config.imdb_dataset_path
+            ),
+            Err(e) => {
+                error!("IMDb import failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if config.tmdb_token.is_some() {
+        init_tmdb_cache(config.tmdb_cache_ttl);
+    }
+
     if let Some(tmdb_token) = &config.tmdb_token {
         match dbs::get_account_username(tmdb_token).await {
             Ok(_username) => {
@@ -75,6 +125,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    dbs::init_resolvers(&config);
+
+    if let Err(e) = ban_words::init_ban_words(&config.ban_words) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+
     if config.use_tor {
         info!(
             "Tor routing enabled (proxy: {})",
@@ -84,9 +141,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Tor routing disabled — connecting to relays directly");
     }
 
+    init_search_cache(
+        std::time::Duration::from_secs(config.cache_ttl),
+        config.cache_max_entries,
+    );
+
+    if let Err(e) = init_trackers(&config).await {
+        error!("Failed to initialize the tracker list: {}. Exiting.", e);
+        std::process::exit(1);
+    }
+
     info!("Ranking Nostr relays by latency...");
     let ranked_relays = rank_relays(config.use_tor, config.tor_proxy.as_deref()).await;
-    if ranked_relays.is_empty() {
+    if ranked_relays.is_empty() && config.local_index {
+        warn!("No Nostr relays are reachable, serving from the local index only");
+    } else if ranked_relays.is_empty() {
         error!(
             "No Nostr relays are reachable, try again later or check your network connection. Exiting."
         );
@@ -102,7 +171,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .join(", ")
     );
 
-    let nostr_client = NostrClient::new(ranked_relays, config.use_tor, config.tor_proxy.clone());
+    let nostr_client = NostrClient::new(
+        ranked_relays,
+        config.use_tor,
+        config.tor_proxy.clone(),
+        config.relay_mode,
+        config.fanout_relays,
+    );
+    info!(
+        "Relay mode: {:?} (fan-out to {} relays)",
+        config.relay_mode, config.fanout_relays
+    );
+    let nostr_data = web::Data::new(nostr_client);
+
+    if config.local_index {
+        let opened = init_local_index(&config.index_path, nostr_data.clone().into_inner()).await;
+        if let Err(e) = opened {
+            error!("Failed to open local index {}: {}", config.index_path, e);
+        }
+    }
+
+    stream::init_live_stream(
+        nostr_data.clone().into_inner(),
+        index::get_local_index().cloned(),
+    );
+    if let Err(e) = watch::init_watch() {
+        error!("{}. Exiting.", e);
+        std::process::exit(1);
+    }
 
     CATEGORIES_CACHE
         .set(init_categories())
@@ -112,7 +208,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         CATEGORIES_CACHE.get().unwrap().len()
     );
 
-    let nostr_data = web::Data::new(nostr_client);
     let config_clone = config.clone();
 
     HttpServer::new(move || {
@@ -1,19 +1,32 @@
 use crate::categories::nostr_tag_to_cat_id;
-use crate::parser::Torrent;
-use futures::{SinkExt, StreamExt};
+use crate::config::RelayMode;
+use crate::index::get_local_index;
+use crate::parser::{ExternalId, Torrent, TorrentDetails, TorrentFile};
+use crate::relay_pool::RelayPool;
+use crate::release::ReleaseInfo;
+use crate::trackers::get_trackers;
+use futures::{SinkExt, Stream, StreamExt};
 use futures_util::stream::FuturesUnordered;
 use secp256k1::{Secp256k1, XOnlyPublicKey};
 use serde_json::{Value, json};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use tokio_socks::tcp::Socks5Stream;
 use tokio_tungstenite::{client_async_tls, connect_async, tungstenite::Message};
 use urlencoding::encode;
 use uuid::Uuid;
 
 // Ygg migration pub key
-const ALLOWED_PUBKEY: &str = "6aeb55064ea8b777591055e5704612e0e863fcc00bb211741781be299473c54e";
+pub const ALLOWED_PUBKEY: &str = "6aeb55064ea8b777591055e5704612e0e863fcc00bb211741781be299473c54e";
+
+/// Live events buffered between the relay subscriptions and the subscriber.
+const LIVE_BUFFER: usize = 256;
+/// Event ids remembered to forward an event delivered by several relays once.
+const LIVE_RECENT_IDS: usize = 512;
 
 /// All known Nostr relays hosting NIP-35 torrent events.
 pub const KNOWN_CLEARNET_RELAYS: &[&str] = &[
@@ -223,18 +236,45 @@ async fn probe_relay(
     }
 }
 
+/// One REQ worth of search results.
+#[derive(Clone)]
+pub struct SearchBatch {
+    pub torrents: Vec<Torrent>,
+    /// Events returned by the relay, before NIP-35 parsing
+    pub event_count: usize,
+    /// Oldest `created_at` in the batch, the `until` bound of the next page
+    pub oldest_created_at: Option<u64>,
+    /// `created_at` of every torrent, by event ID
+    pub created_at: HashMap<String, u64>,
+}
+
 pub struct NostrClient {
     relays: Arc<Mutex<Vec<String>>>,
     use_tor: bool,
     tor_proxy: Option<String>,
+    pool: RelayPool,
+    relay_mode: RelayMode,
+    fanout_relays: usize,
+    /// Relay of the next live subscription in `first` mode
+    live_rotation: AtomicUsize,
 }
 
 impl NostrClient {
-    pub fn new(relays: Vec<String>, use_tor: bool, tor_proxy: Option<String>) -> Self {
+    pub fn new(
+        relays: Vec<String>,
+        use_tor: bool,
+        tor_proxy: Option<String>,
+        relay_mode: RelayMode,
+        fanout_relays: usize,
+    ) -> Self {
         NostrClient {
             relays: Arc::new(Mutex::new(relays)),
             use_tor,
+            pool: RelayPool::new(use_tor, tor_proxy.clone()),
             tor_proxy,
+            relay_mode,
+            fanout_relays,
+            live_rotation: AtomicUsize::new(0),
         }
     }
 
@@ -256,6 +296,10 @@ impl NostrClient {
 
         warn!("All relays died, re-ranking...");
         let fresh = rank_relays(self.use_tor, self.tor_proxy.as_deref()).await;
+        if fresh.is_empty() && get_local_index().is_some() {
+            error!("Re-ranking returned no reachable relays, serving from the local index only");
+            return false;
+        }
         if fresh.is_empty() {
             error!("Re-ranking returned no reachable relays, try again later. Exiting.");
             std::process::exit(1);
@@ -281,9 +325,9 @@ impl NostrClient {
         query: &str,
         tag_filter: Option<&str>,
         limit: usize,
-    ) -> Result<Vec<Torrent>, Box<dyn std::error::Error + Send + Sync>> {
-        let sub_id = Uuid::new_v4().to_string();
-
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>> {
         let mut filter = json!({
             "kinds": [2003],
             "limit": limit
@@ -297,6 +341,42 @@ impl NostrClient {
             filter["#t"] = json!([tag]);
         }
 
+        if let Some(since) = since {
+            filter["since"] = json!(since);
+        }
+
+        if let Some(until) = until {
+            filter["until"] = json!(until);
+        }
+
+        self.search_filter(filter).await
+    }
+
+    /// Search torrent events tagged with any of the `i` tag values (`imdb:tt…`, `tmdb:movie:…`).
+    pub async fn search_external_ids(
+        &self,
+        external_ids: &[String],
+        tag_filter: Option<&str>,
+        limit: usize,
+    ) -> Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let mut filter = json!({
+            "kinds": [2003],
+            "#i": external_ids,
+            "limit": limit
+        });
+
+        if let Some(tag) = tag_filter {
+            filter["#t"] = json!([tag]);
+        }
+
+        self.search_filter(filter).await
+    }
+
+    async fn search_filter(
+        &self,
+        filter: Value,
+    ) -> Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>> {
+        let sub_id = Uuid::new_v4().to_string();
         let req = json!(["REQ", sub_id, filter]);
 
         debug!(
@@ -311,8 +391,19 @@ impl NostrClient {
         );
 
         let events = self.send_req(&sub_id, req).await?;
+        let event_count = events.len();
+        let oldest_created_at = events.iter().filter_map(|e| e["created_at"].as_u64()).min();
+        let created_at = events
+            .iter()
+            .filter_map(|e| Some((e["id"].as_str()?.to_string(), e["created_at"].as_u64()?)))
+            .collect();
         let torrents = events.into_iter().filter_map(parse_nip35_event).collect();
-        Ok(torrents)
+        Ok(SearchBatch {
+            torrents,
+            event_count,
+            oldest_created_at,
+            created_at,
+        })
     }
 
     /// Fetch a single event by ID (used by /torrent/{id}).
@@ -333,9 +424,48 @@ impl NostrClient {
         Ok(events.into_iter().next())
     }
 
+    /// Dispatch a REQ according to the configured relay mode. Fan-out modes fall back to
+    /// the single-relay failover when every queried relay fails.
+    async fn send_req(
+        &self,
+        sub_id: &str,
+        req: Value,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.relay_mode == RelayMode::First || self.fanout_relays < 2 {
+            return self.send_req_first(sub_id, req).await;
+        }
+
+        let relays: Vec<String> = self.relays().into_iter().take(self.fanout_relays).collect();
+        if relays.len() < 2 {
+            return self.send_req_first(sub_id, req).await;
+        }
+
+        match self.send_req_fanout(&relays, sub_id, &req).await {
+            Ok(events) => Ok(events),
+            Err(e) => {
+                warn!("Fan-out to {} relays failed: {}", relays.len(), e);
+                self.send_req_first(sub_id, req).await
+            }
+        }
+    }
+
+    /// Send the REQ to several relays in parallel, see `collect_answers`.
+    async fn send_req_fanout(
+        &self,
+        relays: &[String],
+        sub_id: &str,
+        req: &Value,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        let futures_set: FuturesUnordered<_> = relays
+            .iter()
+            .map(|url| async move { (url.as_str(), self.send_req_to(url, sub_id, req).await) })
+            .collect();
+        collect_answers(self.relay_mode, futures_set).await
+    }
+
     /// Try the best relay. On failure, remove it and try the next one.
     /// Re-ranks if all relays are consumed.
-    async fn send_req(
+    async fn send_req_first(
         &self,
         sub_id: &str,
         req: Value,
@@ -351,8 +481,10 @@ impl NostrClient {
             };
 
             if relay_url.is_empty() {
-                // shouldn't happen
-                self.remove_first_relay().await;
+                // only happens when every relay is down and the local index is enabled
+                if !self.remove_first_relay().await {
+                    return Err("No reachable relay".into());
+                }
                 continue;
             }
 
@@ -363,127 +495,289 @@ impl NostrClient {
                 }
                 Err(e) => {
                     warn!("Relay {} failed: {}", relay_url, e);
-                    self.remove_first_relay().await;
+                    if !self.remove_first_relay().await {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run an arbitrary kind-2003 filter against the best relay (with the usual failover).
+    pub async fn query(
+        &self,
+        filter: Value,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        let sub_id = Uuid::new_v4().to_string();
+        let req = json!(["REQ", sub_id, filter]);
+        self.send_req(&sub_id, req).await
+    }
+
+    /// Open a long-lived subscription and forward every verified event to `tx`, once even
+    /// when several relays deliver it. In `first` mode a single relay is used, the next one
+    /// of the ranking after each drop; the fan-out modes subscribe on the top relays at once.
+    /// Returns once every relay dropped or the receiver is gone; callers are expected to
+    /// resubscribe with an updated `since`.
+    pub async fn subscribe(
+        &self,
+        filter: Value,
+        tx: mpsc::Sender<Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut relays = self.relays();
+        if relays.is_empty() {
+            return Err("No relay available".into());
+        }
+        match self.relay_mode {
+            RelayMode::First => {
+                let next = self.live_rotation.fetch_add(1, Ordering::Relaxed) % relays.len();
+                relays = vec![relays.swap_remove(next)];
+            }
+            RelayMode::Fastest | RelayMode::All => relays.truncate(self.fanout_relays.max(1)),
+        }
+
+        let (relay_tx, mut relay_rx) = mpsc::channel::<Value>(LIVE_BUFFER);
+        let subscriptions: FuturesUnordered<_> = relays
+            .iter()
+            .map(|url| {
+                let relay_tx = relay_tx.clone();
+                let filter = &filter;
+                async move {
+                    if let Err(e) = self.subscribe_to(url, filter, relay_tx).await {
+                        warn!("Live subscription on {} ended: {}", url, e);
+                    }
+                }
+            })
+            .collect();
+        drop(relay_tx);
+
+        // Ends once every subscription dropped its sender, or when `tx` is closed
+        let forward = async {
+            let mut recent: VecDeque<String> = VecDeque::with_capacity(LIVE_RECENT_IDS);
+            while let Some(event) = relay_rx.recv().await {
+                let id = event["id"].as_str().unwrap_or_default().to_string();
+                if recent.contains(&id) {
+                    continue;
+                }
+                if recent.len() == LIVE_RECENT_IDS {
+                    recent.pop_front();
+                }
+                recent.push_back(id);
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::select! {
+            _ = forward => {}
+            _ = async {
+                subscriptions.collect::<Vec<()>>().await;
+                std::future::pending::<()>().await
+            } => {}
+        }
+
+        Err(format!("Subscription to {} ended", relays.join(", ")).into())
+    }
+
+    /// Forward the verified events of a subscription on one relay to `tx`.
+    async fn subscribe_to(
+        &self,
+        relay_url: &str,
+        filter: &Value,
+        tx: mpsc::Sender<Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let sub_id = Uuid::new_v4().to_string();
+        let req = json!(["REQ", sub_id, filter]);
+        let conn = self.pool.get(relay_url).await?;
+        let mut subscription = conn.open(&sub_id, &req)?;
+        info!("Subscribed to live events on {}", relay_url);
+
+        while let Some(msg) = subscription.recv().await {
+            match msg.get(0).and_then(|v| v.as_str()) {
+                Some("EVENT") => {
+                    let Some(event) = msg.get(2).and_then(accept_event) else {
+                        continue;
+                    };
+                    if tx.send(event).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Some("CLOSED") => {
+                    warn!("Relay {} closed the subscription: {}", relay_url, msg);
+                    break;
                 }
+                _ => {}
             }
         }
+
+        Err(format!("Subscription to {} ended", relay_url).into())
     }
 
-    /// Open a WebSocket to a single relay, send a REQ, collect EVENTs until EOSE or timeout.
+    /// Send a REQ to a single relay over its pooled connection and collect EVENTs until EOSE
+    /// or timeout. A pooled connection that turns out to be dead is reopened once.
     async fn send_req_to(
         &self,
         relay_url: &str,
         sub_id: &str,
         req: &Value,
     ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
-        debug!("Connecting to relay: {}", relay_url);
-
-        let url = url::Url::parse(relay_url)?;
-        let host = url.host_str().unwrap().to_string();
-        let port = url.port().unwrap_or(80);
-
-        let req_text = req.to_string();
-
-        macro_rules! collect_events {
-            ($write:expr, $read:expr) => {{
-                let mut write = $write;
-                let mut read = $read;
-                write.send(Message::Text(req_text.into())).await?;
-
-                let mut events: Vec<Value> = Vec::new();
-
-                let timeout = tokio::time::timeout(Duration::from_secs(30), async {
-                    while let Some(msg) = read.next().await {
-                        match msg {
-                            Ok(Message::Text(text)) => {
-                                let Ok(parsed) = serde_json::from_str::<Value>(&text) else {
-                                    continue;
-                                };
-                                let arr = match parsed.as_array() {
-                                    Some(a) => a,
-                                    None => continue,
-                                };
-                                match arr.first().and_then(|v| v.as_str()) {
-                                    Some("EVENT") => {
-                                        if arr.get(1).and_then(|v| v.as_str()) == Some(sub_id) {
-                                            if let Some(event) = arr.get(2) {
-                                                let pubkey = event["pubkey"].as_str().unwrap_or("");
-                                                if pubkey != ALLOWED_PUBKEY {
-                                                    debug!(
-                                                        "Dropped event from unauthorized pubkey: {}",
-                                                        pubkey
-                                                    );
-                                                } else if verify_event(event) {
-                                                    events.push(event.clone());
-                                                } else {
-                                                    warn!(
-                                                        "Dropped event with invalid signature: {:?}",
-                                                        event["id"]
-                                                    );
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Some("EOSE") => break,
-                                    _ => {}
-                                }
-                            }
-                            Ok(Message::Close(_)) => break,
-                            Err(e) => {
-                                debug!("WebSocket error: {}", e);
-                                break;
-                            }
-                            _ => {}
-                        }
-                    }
-                })
-                .await;
-
-                if timeout.is_err() {
+        let mut retried = false;
+        loop {
+            match self.collect_events(relay_url, sub_id, req).await {
+                Err(e) if !retried => {
                     debug!(
-                        "Nostr relay timeout after 30s, returning {} events collected so far",
-                        events.len()
+                        "Pooled connection to {} failed ({}), reconnecting",
+                        relay_url, e
                     );
+                    self.pool.evict(relay_url);
+                    retried = true;
                 }
+                result => return result,
+            }
+        }
+    }
 
-                let close_msg = json!(["CLOSE", sub_id]);
-                let _ = write.send(Message::Text(close_msg.to_string().into())).await;
-                let _ = write.close().await;
-
-                events
-            }};
+    async fn collect_events(
+        &self,
+        relay_url: &str,
+        sub_id: &str,
+        req: &Value,
+    ) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.pool.get(relay_url).await?;
+        let mut subscription = conn.open(sub_id, req)?;
+
+        let mut events: Vec<Value> = Vec::new();
+        let mut complete = false;
+
+        let timeout = tokio::time::timeout(Duration::from_secs(30), async {
+            while let Some(msg) = subscription.recv().await {
+                match msg.get(0).and_then(|v| v.as_str()) {
+                    Some("EVENT") => {
+                        if let Some(event) = msg.get(2).and_then(accept_event) {
+                            events.push(event);
+                        }
+                    }
+                    Some("EOSE") | Some("CLOSED") => {
+                        complete = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await;
+        drop(subscription);
+
+        if timeout.is_err() {
+            debug!(
+                "Nostr relay timeout after 30s, returning {} events collected so far",
+                events.len()
+            );
+        } else if !complete && events.is_empty() {
+            return Err(format!("Connection to {} lost", relay_url).into());
         }
 
-        if self.use_tor {
-            let proxy_addr = self.tor_proxy.as_deref().unwrap_or("127.0.0.1:9050");
-            info!("Connecting to {} via Tor proxy {}", relay_url, proxy_addr);
+        Ok(events)
+    }
+}
 
-            let socks_stream = Socks5Stream::connect(proxy_addr, (host.as_str(), port))
-                .await
-                .map_err(|e| format!("Failed to connect via Tor to {}: {}", relay_url, e))?;
+/// Gather the relay answers as they arrive. In `fastest` mode the first non-empty answer
+/// wins, an empty one only counts once every relay answered empty; in `all` mode every
+/// answer is merged and deduplicated. Fails when every relay failed.
+async fn collect_answers<'a, E: std::fmt::Display>(
+    relay_mode: RelayMode,
+    mut answers: impl Stream<Item = (&'a str, Result<Vec<Value>, E>)> + Unpin,
+) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut answered: Vec<Vec<Value>> = Vec::new();
+    while let Some((url, result)) = answers.next().await {
+        match result {
+            Ok(events) => {
+                info!("Got {} events from {}", events.len(), url);
+                if relay_mode == RelayMode::Fastest && !events.is_empty() {
+                    return Ok(events);
+                }
+                answered.push(events);
+            }
+            Err(e) => warn!("Relay {} failed: {}", url, e),
+        }
+    }
 
-            let (ws_stream, _) = client_async_tls(relay_url, socks_stream)
-                .await
-                .map_err(|e| format!("WebSocket handshake failed for {}: {}", relay_url, e))?;
+    if answered.is_empty() {
+        return Err("every relay failed".into());
+    }
+    let count = answered.len();
+    let merged = merge_events(answered);
+    info!(
+        "Merged {} unique events from {} relays",
+        merged.len(),
+        count
+    );
+    Ok(merged)
+}
 
-            let (write, read) = ws_stream.split();
-            Ok(collect_events!(write, read))
-        } else {
-            debug!("Connecting to {} directly (Tor disabled)", relay_url);
-            let (ws_stream, _) = connect_async(relay_url)
-                .await
-                .map_err(|e| format!("Failed to connect to {}: {}", relay_url, e))?;
+/// Merge event lists from several relays: events are deduplicated by id, and for replaceable
+/// kinds (NIP-01) only the newest version per pubkey/kind (and `d` tag) is kept.
+fn merge_events(answers: Vec<Vec<Value>>) -> Vec<Value> {
+    let mut by_key: HashMap<String, Value> = HashMap::new();
+
+    for event in answers.into_iter().flatten() {
+        let kind = event["kind"].as_u64().unwrap_or(0);
+        let pubkey = event["pubkey"].as_str().unwrap_or("");
+        let key = match kind {
+            0 | 3 | 10000..=19999 => format!("{}:{}", kind, pubkey),
+            30000..=39999 => {
+                let d = event["tags"]
+                    .as_array()
+                    .and_then(|tags| {
+                        tags.iter().find_map(|t| {
+                            let arr = t.as_array()?;
+                            if arr.first()?.as_str()? == "d" {
+                                arr.get(1)?.as_str()
+                            } else {
+                                None
+                            }
+                        })
+                    })
+                    .unwrap_or("");
+                format!("{}:{}:{}", kind, pubkey, d)
+            }
+            _ => match event["id"].as_str() {
+                Some(id) => id.to_string(),
+                None => continue,
+            },
+        };
 
-            let (write, read) = ws_stream.split();
-            Ok(collect_events!(write, read))
+        let created_at = event["created_at"].as_u64().unwrap_or(0);
+        match by_key.get(&key) {
+            Some(existing) if existing["created_at"].as_u64().unwrap_or(0) >= created_at => {}
+            _ => {
+                by_key.insert(key, event);
+            }
         }
     }
+
+    let mut events: Vec<Value> = by_key.into_values().collect();
+    events.sort_by_key(|e| std::cmp::Reverse(e["created_at"].as_u64().unwrap_or(0)));
+    events
+}
+
+/// Keep an incoming event only if it comes from the Ygg pubkey and its signature is valid.
+fn accept_event(event: &Value) -> Option<Value> {
+    let pubkey = event["pubkey"].as_str().unwrap_or("");
+    if pubkey != ALLOWED_PUBKEY {
+        debug!("Dropped event from unauthorized pubkey: {}", pubkey);
+        None
+    } else if verify_event(event) {
+        Some(event.clone())
+    } else {
+        warn!("Dropped event with invalid signature: {:?}", event["id"]);
+        None
+    }
 }
 
 /// Verify a Nostr event:
 /// 1. Recompute id = SHA-256([0, pubkey, created_at, kind, tags, content])
 /// 2. Verify the Schnorr signature (BIP-340) of id with pubkey.
-fn verify_event(event: &Value) -> bool {
+pub(crate) fn verify_event(event: &Value) -> bool {
     let pubkey_hex = match event["pubkey"].as_str() {
         Some(s) => s,
         None => return false,
@@ -572,8 +866,73 @@ fn verify_event(event: &Value) -> bool {
     }
 }
 
+/// Parse a NIP-35 Kind 2003 Nostr event with the file list, description and every tag.
+pub(crate) fn parse_nip35_details(event: &Value) -> Option<TorrentDetails> {
+    let torrent = parse_nip35_event(event.clone())?;
+    let tags: Vec<Vec<String>> = event["tags"]
+        .as_array()?
+        .iter()
+        .filter_map(|t| {
+            t.as_array().map(|arr| {
+                arr.iter()
+                    .map(|v| {
+                        v.as_str()
+                            .map(str::to_string)
+                            .unwrap_or_else(|| v.to_string())
+                    })
+                    .collect()
+            })
+        })
+        .collect();
+
+    let values = |name: &str| -> Vec<&Vec<String>> {
+        tags.iter()
+            .filter(|t| t.first().map(String::as_str) == Some(name) && t.len() > 1)
+            .collect()
+    };
+
+    let files = values("file")
+        .into_iter()
+        .map(|t| TorrentFile {
+            name: t[1].clone(),
+            size: t.get(2).and_then(|s| s.parse().ok()),
+        })
+        .collect();
+    let hashtags = values("t").into_iter().map(|t| t[1].clone()).collect();
+    let trackers = event_trackers(event["tags"].as_array()?);
+    let published_at = values("published_at")
+        .first()
+        .and_then(|t| t[1].parse().ok());
+
+    Some(TorrentDetails {
+        torrent,
+        description: event["content"].as_str().unwrap_or("").to_string(),
+        files,
+        hashtags,
+        trackers,
+        created_at: event["created_at"].as_u64().unwrap_or(0),
+        published_at,
+        pubkey: event["pubkey"].as_str().unwrap_or("").to_string(),
+        signature_valid: verify_event(event),
+        tags,
+    })
+}
+
+/// Announce URLs of the `tracker` (NIP-35) and `tr` tags of an event.
+fn event_trackers(tags: &[Value]) -> Vec<String> {
+    tags.iter()
+        .filter_map(|t| {
+            let arr = t.as_array()?;
+            match arr.first()?.as_str()? {
+                "tracker" | "tr" => arr.get(1)?.as_str().map(|s| s.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 /// Parse a NIP-35 Kind 2003 Nostr event into a Torrent struct.
-fn parse_nip35_event(event: Value) -> Option<Torrent> {
+pub(crate) fn parse_nip35_event(event: Value) -> Option<Torrent> {
     let tags = event["tags"].as_array()?;
     let event_id = event["id"].as_str()?.to_string();
     let created_at = event["created_at"].as_u64().unwrap_or(0) as usize;
@@ -590,7 +949,7 @@ fn parse_nip35_event(event: Value) -> Option<Torrent> {
     };
 
     let name = get_tag("title").or_else(|| get_tag("name"))?;
-    let infohash = get_tag("x")?;
+    let infohash = get_tag("x")?.to_lowercase();
 
     let size: u64 = get_tag("size").and_then(|s| s.parse().ok()).unwrap_or(0);
 
@@ -647,59 +1006,24 @@ fn parse_nip35_event(event: Value) -> Option<Torrent> {
         })
         .unwrap_or(0);
 
-    // Build magnet link using the same hardcoded tracker list as ygg.gratis
-    const MAGNET_TRACKERS: &[&str] = &[
-        "https://tracker.yggleak.top/announce",
-        "udp://tracker.opentrackr.org:1337/announce",
-        "udp://open.demonii.com:1337/announce",
-        "udp://open.stealth.si:80/announce",
-        "udp://exodus.desync.com:6969/announce",
-        "https://torrent.tracker.durukanbal.com:443/announce",
-        "udp://tracker1.myporn.club:9337/announce",
-        "udp://tracker.torrent.eu.org:451/announce",
-        "udp://tracker.theoks.net:6969/announce",
-        "udp://tracker.srv00.com:6969/announce",
-        "udp://tracker.filemail.com:6969/announce",
-        "udp://tracker.dler.org:6969/announce",
-        "udp://tracker.corpscorp.online:80/announce",
-        "udp://tracker.alaskantf.com:6969/announce",
-        "udp://tracker-udp.gbitt.info:80/announce",
-        "udp://t.overflow.biz:6969/announce",
-        "udp://open.dstud.io:6969/announce",
-        "udp://leet-tracker.moe:1337/announce",
-        "udp://explodie.org:6969/announce",
-        "udp://bittorrent-tracker.e-n-c-r-y-p-t.net:1337/announce",
-        "udp://6ahddutb1ucc3cp.ru:6969/announce",
-        "udp://94.23.207.177:6969/announce",
-        "udp://37.59.48.81:6969/announce",
-        "udp://54.36.179.216:6969/announce",
-        "udp://193.42.111.57:9337/announce",
-        "udp://43.250.54.137:6969/announce",
-        "udp://91.216.110.53:451/announce",
-        "udp://45.134.88.121:6969/announce",
-        "udp://135.125.236.64:6969/announce",
-        "udp://5.255.124.190:6969/announce",
-        "udp://93.158.213.92:1337/announce",
-        "udp://107.189.4.235:1337/announce",
-        "udp://tracker.qu.ax:6969/announce",
-        "udp://107.189.7.165:6969/announce",
-        "udp://103.251.166.126:6969/announce",
-        "udp://185.243.218.213:80/announce",
-        "http://tracker.zhuqiy.com:80/announce",
-        "udp://81.230.84.201:6969/announce",
-        "udp://212.42.38.197:6969/announce",
-        "http://193.31.26.113:6969/announce",
-        "udp://176.99.7.59:6969/announce",
-        "http://tr.nyacat.pw:80/announce",
-    ];
-
     let mut magnet = format!("magnet:?xt=urn:btih:{}&dn={}", infohash, encode(&name));
-    for tracker in MAGNET_TRACKERS {
-        magnet.push_str(&format!("&tr={}", encode(tracker)));
+    for tracker in get_trackers().resolve(&event_trackers(tags)) {
+        magnet.push_str(&format!("&tr={}", encode(&tracker)));
     }
 
     let link = format!("https://ygg.gratis/#/torrent/{}", event_id);
 
+    let external_ids = tags
+        .iter()
+        .filter_map(|t| {
+            let arr = t.as_array()?;
+            match arr.first()?.as_str()? {
+                "i" => ExternalId::parse(arr.get(1)?.as_str()?),
+                _ => None,
+            }
+        })
+        .collect();
+
     // Prefer published_at tag over event created_at (mirrors ygg.gratis behaviour)
     let age_stamp = get_tag("published_at")
         .and_then(|s| s.parse().ok())
@@ -707,7 +1031,9 @@ fn parse_nip35_event(event: Value) -> Option<Torrent> {
 
     Some(Torrent {
         id: event_id.clone(),
+        release: ReleaseInfo::parse(&name),
         name,
+        infohash,
         category_id,
         age_stamp,
         size,
@@ -717,10 +1043,114 @@ fn parse_nip35_event(event: Value) -> Option<Torrent> {
         magnet,
         link,
         file_count,
+        external_ids,
+        score: None,
     })
 }
 
-/// Build a magnet URI from a raw NIP-35 event Value (used by /torrent/{id}).
-pub fn magnet_from_event(event: &Value) -> Option<String> {
-    parse_nip35_event(event.clone()).map(|t| t.magnet)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_events_dedupes_and_keeps_newest_replaceable() {
+        let torrent = json!({"id": "a", "kind": 2003, "pubkey": "p", "created_at": 10});
+        let old_profile = json!({"id": "b", "kind": 0, "pubkey": "p", "created_at": 5});
+        let new_profile = json!({"id": "c", "kind": 0, "pubkey": "p", "created_at": 20});
+
+        let merged = merge_events(vec![
+            vec![torrent.clone(), old_profile],
+            vec![torrent, new_profile],
+        ]);
+
+        let ids: Vec<&str> = merged.iter().filter_map(|e| e["id"].as_str()).collect();
+        assert_eq!(ids, vec!["c", "a"]);
+    }
+
+    #[test]
+    fn test_merge_events_keeps_newest_per_d_tag() {
+        let list = |id: &str, d: &str, created_at: u64| json!({"id": id, "kind": 30000, "pubkey": "p", "created_at": created_at, "tags": [["d", d]]});
+
+        let merged = merge_events(vec![
+            vec![list("a", "x", 10), list("b", "y", 10)],
+            vec![list("c", "x", 20), list("d", "y", 5)],
+        ]);
+
+        let mut ids: Vec<&str> = merged.iter().filter_map(|e| e["id"].as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_answers() {
+        let event = |id: &str| json!({"id": id, "kind": 2003, "pubkey": "p", "created_at": 1});
+        let answers = || {
+            futures::stream::iter(vec![
+                ("ws://a", Err("down".to_string())),
+                ("ws://b", Ok(vec![])),
+                ("ws://c", Ok(vec![event("1")])),
+                ("ws://d", Ok(vec![event("1"), event("2")])),
+            ])
+        };
+        let ids = |events: Vec<Value>| {
+            let mut ids: Vec<String> = events.iter().map(|e| e["id"].to_string()).collect();
+            ids.sort();
+            ids
+        };
+
+        // The empty answer of b doesn't win
+        let fastest = collect_answers(RelayMode::Fastest, answers())
+            .await
+            .unwrap();
+        assert_eq!(ids(fastest), vec!["\"1\""]);
+        let all = collect_answers(RelayMode::All, answers()).await.unwrap();
+        assert_eq!(ids(all), vec!["\"1\"", "\"2\""]);
+
+        let empty = futures::stream::iter(vec![
+            ("ws://a", Ok::<_, String>(vec![])),
+            ("ws://b", Ok(vec![])),
+        ]);
+        assert!(
+            collect_answers(RelayMode::Fastest, empty)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let failed = futures::stream::iter(vec![("ws://a", Err::<Vec<Value>, _>("down"))]);
+        assert!(collect_answers(RelayMode::Fastest, failed).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_nip35_details() {
+        crate::trackers::init_test_trackers();
+        let event = json!({
+            "id": "abc",
+            "kind": 2003,
+            "pubkey": ALLOWED_PUBKEY,
+            "created_at": 1700000000,
+            "content": "Description",
+            "sig": "00",
+            "tags": [
+                ["title", "Moana.2.2024.MULTi.1080p"],
+                ["x", "0123456789ABCDEF0123456789ABCDEF01234567"],
+                ["file", "Moana.2.mkv", "3000000000"],
+                ["file", "Moana.2.nfo"],
+                ["i", "imdb:tt13622970"],
+                ["i", "tmdb:movie:1241982"],
+                ["t", "film"],
+                ["published_at", "1699999999"]
+            ]
+        });
+
+        let details = parse_nip35_details(&event).unwrap();
+        assert_eq!(details.torrent.file_count, 2);
+        assert_eq!(details.files[0].size, Some(3000000000));
+        assert_eq!(details.files[1].size, None);
+        assert_eq!(details.torrent.external_ids[1].source, "tmdb");
+        assert_eq!(details.torrent.external_ids[1].id, "movie:1241982");
+        assert_eq!(details.hashtags, vec!["film"]);
+        assert_eq!(details.published_at, Some(1699999999));
+        assert!(!details.signature_valid);
+        assert_eq!(details.tags.len(), 8);
+    }
 }
@@ -1,13 +1,16 @@
+use crate::ranking::Score;
+use crate::release::ReleaseInfo;
 use crate::search::{Order, Sort};
 use serde::Serialize;
 use serde_json::Value;
 use std::cmp::PartialEq;
 
-#[derive(Debug, Serialize, Clone, Eq, Hash, PartialEq)]
+#[derive(Debug, Default, Serialize, Clone, Eq, Hash, PartialEq)]
 pub struct Torrent {
     pub id: String,
     pub category_id: usize,
     pub name: String,
+    pub infohash: String,
     pub age_stamp: usize,
     pub size: u64,
     pub completed: usize,
@@ -16,6 +19,85 @@ pub struct Torrent {
     pub magnet: String,
     pub link: String,
     pub file_count: usize,
+    /// `i` tag references (IMDb, TMDB, ...)
+    pub external_ids: Vec<ExternalId>,
+    /// Quality metadata parsed from `name`
+    pub release: ReleaseInfo,
+    /// Relevance score, only set with `sort=relevance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+/// Everything a NIP-35 event carries about a torrent, for the details endpoint.
+#[derive(Debug, Serialize, Clone)]
+pub struct TorrentDetails {
+    #[serde(flatten)]
+    pub torrent: Torrent,
+    pub description: String,
+    pub files: Vec<TorrentFile>,
+    pub hashtags: Vec<String>,
+    pub trackers: Vec<String>,
+    pub created_at: u64,
+    pub published_at: Option<u64>,
+    pub pubkey: String,
+    /// Always true when served, events with a bad ID or signature are dropped on receipt
+    pub signature_valid: bool,
+    /// Raw event tags, unmodified
+    pub tags: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TorrentFile {
+    pub name: String,
+    pub size: Option<u64>,
+}
+
+/// An `i` tag reference, e.g. `imdb:tt1234567` → `{source: "imdb", id: "tt1234567"}`
+/// or `tmdb:movie:693134` → `{source: "tmdb", id: "movie:693134"}`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct ExternalId {
+    pub source: String,
+    pub id: String,
+}
+
+impl ExternalId {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (source, id) = value.split_once(':')?;
+        if source.is_empty() || id.is_empty() {
+            return None;
+        }
+        Some(ExternalId {
+            source: source.to_lowercase(),
+            id: id.to_string(),
+        })
+    }
+
+    /// `imdb:tt1234567`, the `tt` prefix is added when missing.
+    pub fn imdb(id: &str) -> Self {
+        let id = id.trim().to_lowercase();
+        ExternalId {
+            source: "imdb".to_string(),
+            id: match id.starts_with("tt") {
+                true => id,
+                false => format!("tt{}", id),
+            },
+        }
+    }
+
+    /// `tmdb:movie:693134` or `tmdb:tv:1399`.
+    pub fn tmdb(id: &str, tv: bool) -> Self {
+        let kind = if tv { "tv" } else { "movie" };
+        ExternalId {
+            source: "tmdb".to_string(),
+            id: format!("{}:{}", kind, id.trim()),
+        }
+    }
+}
+
+impl std::fmt::Display for ExternalId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.source, self.id)
+    }
 }
 
 impl PartialEq for Order {
@@ -28,11 +110,21 @@ impl PartialEq for Order {
 }
 
 impl Torrent {
+    /// Test fixture: a torrent with only a name and its parsed release.
+    #[cfg(test)]
+    pub fn named(name: &str) -> Self {
+        Torrent {
+            name: name.to_string(),
+            release: ReleaseInfo::parse(name),
+            ..Default::default()
+        }
+    }
+
     pub fn to_json(&self) -> Value {
         serde_json::to_value(self).unwrap()
     }
 
-    pub fn sort(torrents: &mut Vec<Torrent>, sort: Option<Sort>, order: Option<Order>) {
+    pub fn sort(torrents: &mut [Torrent], sort: Option<Sort>, order: Option<Order>) {
         let sort = sort.unwrap_or(Sort::PublishDate);
         let order = order.unwrap_or(Order::Descending);
 
@@ -80,6 +172,14 @@ impl Torrent {
                     torrents.sort_by(|a, b| b.leech.cmp(&a.leech));
                 }
             }
+            Sort::Relevance => {
+                let score = |t: &Torrent| t.score.map_or(f64::MIN, |s| s.0);
+                if order == Order::Ascending {
+                    torrents.sort_by(|a, b| score(a).total_cmp(&score(b)));
+                } else {
+                    torrents.sort_by(|a, b| score(b).total_cmp(&score(a)));
+                }
+            }
         }
     }
 }
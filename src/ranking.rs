@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::filters::normalize;
+use crate::parser::Torrent;
+use crate::search::{Order, Sort, token_coverage, tokenize};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the profile used when `sort=relevance` comes without `profile`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Weights of a `sort=relevance` ranking. Each criterion scores between 0 and 1 before its
+/// weight is applied, tags add their weight as-is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingProfile {
+    /// Share of the query words found in the name
+    pub text: f64,
+    /// Seeders on a log scale, 1 from 1000 seeders
+    pub seeders: f64,
+    /// Publication age, halved every `age_half_life_days`
+    pub age: f64,
+    pub age_half_life_days: f64,
+    /// Closeness to `size_target_gb`, 0 beyond 4 times smaller or larger
+    pub size: f64,
+    pub size_target_gb: Option<f64>,
+    /// Release tags (`MULTi`, `1080p`, `CAM`...) and the score they add, negative to penalize
+    pub tags: BTreeMap<String, f64>,
+}
+
+impl Default for RankingProfile {
+    fn default() -> Self {
+        RankingProfile {
+            text: 10.0,
+            seeders: 3.0,
+            age: 1.0,
+            age_half_life_days: 30.0,
+            size: 0.0,
+            size_target_gb: None,
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+/// The configured profile `name`, the built-in one for `default` unless overridden.
+pub fn find_profile(config: &Config, name: Option<&str>) -> Result<RankingProfile, String> {
+    let name = name.unwrap_or(DEFAULT_PROFILE);
+    match config.ranking_profiles.get(name) {
+        Some(profile) => Ok(profile.clone()),
+        None if name == DEFAULT_PROFILE => Ok(RankingProfile::default()),
+        None => Err(format!("Unknown ranking profile {}", name)),
+    }
+}
+
+/// A relevance score, rounded to 3 decimals.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(transparent)]
+pub struct Score(pub f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Score {}
+
+impl Hash for Score {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// A profile applied to the results of one query.
+pub struct Ranking {
+    profile: RankingProfile,
+    query_tokens: Vec<String>,
+    /// Tags of the profile mapped to their canonical spelling
+    tags: Vec<(String, f64)>,
+    order: Option<Order>,
+    now: u64,
+}
+
+impl Ranking {
+    pub fn new(profile: RankingProfile, query: &str, order: Option<Order>) -> Self {
+        let tags = profile
+            .tags
+            .iter()
+            .map(|(tag, weight)| (normalize(tag), *weight))
+            .collect();
+        Ranking {
+            query_tokens: tokenize(query),
+            tags,
+            profile,
+            order,
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn score(&self, torrent: &Torrent) -> f64 {
+        let profile = &self.profile;
+        let text = match self.query_tokens.len() {
+            0 => 0.0,
+            n => token_coverage(&self.query_tokens, &torrent.name) as f64 / n as f64,
+        };
+        let seeders = ((1.0 + torrent.seed as f64).log10() / 3.0).min(1.0);
+        let age = match profile.age_half_life_days {
+            half_life if half_life > 0.0 => {
+                let days = self.now.saturating_sub(torrent.age_stamp as u64) as f64 / 86400.0;
+                0.5f64.powf(days / half_life)
+            }
+            _ => 0.0,
+        };
+        let size = match profile.size_target_gb {
+            Some(target) if target > 0.0 && torrent.size > 0 => {
+                let ratio = torrent.size as f64 / (target * (1u64 << 30) as f64);
+                (1.0 - ratio.log2().abs() / 2.0).max(0.0)
+            }
+            _ => 0.0,
+        };
+
+        let release = &torrent.release;
+        let values = [
+            &release.resolution,
+            &release.source,
+            &release.codec,
+            &release.group,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(&release.audio)
+        .chain(&release.languages)
+        .chain(&release.hdr);
+        let mut tags = 0.0;
+        for value in values {
+            tags += self
+                .tags
+                .iter()
+                .filter(|(tag, _)| tag.eq_ignore_ascii_case(value))
+                .map(|(_, weight)| weight)
+                .sum::<f64>();
+        }
+
+        profile.text * text
+            + profile.seeders * seeders
+            + profile.age * age
+            + profile.size * size
+            + tags
+    }
+
+    /// Score every torrent and sort them, best first unless `order=ascending`.
+    pub fn rank(&self, torrents: &mut [Torrent]) {
+        for torrent in torrents.iter_mut() {
+            let score = (self.score(torrent) * 1000.0).round() / 1000.0;
+            torrent.score = Some(Score(score));
+        }
+        Torrent::sort(torrents, Some(Sort::Relevance), self.order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(name: &str, seed: usize) -> Torrent {
+        Torrent {
+            id: name.to_string(),
+            size: 8 << 30,
+            seed,
+            ..Torrent::named(name)
+        }
+    }
+
+    #[test]
+    fn test_rank_with_tags() {
+        let profile = RankingProfile {
+            age: 0.0,
+            size: 1.0,
+            size_target_gb: Some(8.0),
+            tags: [("multi", 3.0), ("1080p", 2.0), ("CAM", -10.0)]
+                .into_iter()
+                .map(|(tag, weight)| (tag.to_string(), weight))
+                .collect(),
+            ..Default::default()
+        };
+        let mut torrents = vec![
+            torrent("Dune.2021.CAM.x264-GRP", 999),
+            torrent("Dune.2021.VOSTFR.720p.WEB-DL.x264-GRP", 999),
+            torrent("Dune.2021.MULTi.1080p.WEB-DL.x265-GRP", 9),
+            torrent("Other.2021.MULTi.1080p.WEB-DL.x265-GRP", 9),
+        ];
+        Ranking::new(profile, "dune", None).rank(&mut torrents);
+
+        let names: Vec<&str> = torrents.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Dune.2021.MULTi.1080p.WEB-DL.x265-GRP",
+                "Dune.2021.VOSTFR.720p.WEB-DL.x264-GRP",
+                "Other.2021.MULTi.1080p.WEB-DL.x265-GRP",
+                "Dune.2021.CAM.x264-GRP",
+            ]
+        );
+        // text 10 + seeders 3 * 1/3 + size 1 + tags 5
+        assert_eq!(torrents[0].score, Some(Score(17.0)));
+    }
+}
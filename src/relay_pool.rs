@@ -0,0 +1,373 @@
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OnceCell, mpsc};
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{client_async_tls, connect_async};
+
+type WsSink = Pin<Box<dyn Sink<Message, Error = tungstenite::Error> + Send>>;
+type WsSource = Pin<Box<dyn Stream<Item = Result<Message, tungstenite::Error>> + Send>>;
+
+/// Interval between keepalive pings on idle connections.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// A connection that hasn't received anything (not even a pong) for this long is considered dead.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Time allowed to open the WebSocket, handshake included.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Onion services take longer to reach.
+const TOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A single long-lived WebSocket to a relay. Subscriptions are multiplexed over it by `sub_id`:
+/// every relay message whose second element is a registered `sub_id` is forwarded to that
+/// subscription's channel.
+pub struct RelayConnection {
+    url: String,
+    outgoing: mpsc::UnboundedSender<Message>,
+    subscriptions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+    alive: Arc<AtomicBool>,
+}
+
+impl RelayConnection {
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    /// Register a subscription and send its REQ. Relay messages for `sub_id`
+    /// (`EVENT`, `EOSE`, `CLOSED`) are delivered through the returned `Subscription`,
+    /// which closes itself on the relay when dropped.
+    pub fn open(
+        self: &Arc<Self>,
+        sub_id: &str,
+        req: &Value,
+    ) -> Result<Subscription, Box<dyn std::error::Error + Send + Sync>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .insert(sub_id.to_string(), tx);
+        if self
+            .outgoing
+            .send(Message::Text(req.to_string().into()))
+            .is_err()
+        {
+            self.subscriptions.lock().unwrap().remove(sub_id);
+            return Err(format!("Connection to {} is closed", self.url).into());
+        }
+        Ok(Subscription {
+            sub_id: sub_id.to_string(),
+            conn: self.clone(),
+            messages: rx,
+        })
+    }
+
+    /// Unregister a subscription and tell the relay to close it.
+    fn close(&self, sub_id: &str) {
+        if self.subscriptions.lock().unwrap().remove(sub_id).is_some() {
+            let close_msg = serde_json::json!(["CLOSE", sub_id]);
+            let _ = self
+                .outgoing
+                .send(Message::Text(close_msg.to_string().into()));
+        }
+    }
+}
+
+/// An open REQ on a pooled connection.
+pub struct Subscription {
+    sub_id: String,
+    conn: Arc<RelayConnection>,
+    messages: mpsc::UnboundedReceiver<Value>,
+}
+
+impl Subscription {
+    /// Next relay message for this subscription, `None` once the connection dropped.
+    pub async fn recv(&mut self) -> Option<Value> {
+        self.messages.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.conn.close(&self.sub_id);
+    }
+}
+
+/// Slot of a relay in the pool. Concurrent requests for a cold relay wait on the same
+/// connect instead of each opening a socket.
+type Slot = Arc<OnceCell<Arc<RelayConnection>>>;
+
+/// Pool of long-lived relay connections, opened lazily and reopened after they drop.
+pub struct RelayPool {
+    use_tor: bool,
+    tor_proxy: Option<String>,
+    connect_timeout: Duration,
+    connections: Mutex<HashMap<String, Slot>>,
+}
+
+impl RelayPool {
+    pub fn new(use_tor: bool, tor_proxy: Option<String>) -> Self {
+        RelayPool {
+            use_tor,
+            tor_proxy,
+            connect_timeout: match use_tor {
+                true => TOR_CONNECT_TIMEOUT,
+                false => CONNECT_TIMEOUT,
+            },
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the live connection to `relay_url`, connecting if there is none.
+    pub async fn get(
+        &self,
+        relay_url: &str,
+    ) -> Result<Arc<RelayConnection>, Box<dyn std::error::Error + Send + Sync>> {
+        let slot = {
+            let mut connections = self.connections.lock().unwrap();
+            let slot = connections.entry(relay_url.to_string()).or_default();
+            if slot.get().is_some_and(|conn| !conn.is_alive()) {
+                *slot = Slot::default();
+            }
+            slot.clone()
+        };
+
+        // A failed connect leaves the slot empty, the next request tries again
+        let conn = slot
+            .get_or_try_init(|| async {
+                let (write, read) =
+                    tokio::time::timeout(self.connect_timeout, self.connect(relay_url))
+                        .await
+                        .map_err(|_| format!("Connection to {} timed out", relay_url))??;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+                    self.spawn(relay_url, write, read),
+                )
+            })
+            .await?;
+        Ok(conn.clone())
+    }
+
+    /// Drop the pooled connection to `relay_url` so the next request reconnects.
+    pub fn evict(&self, relay_url: &str) {
+        let slot = self.connections.lock().unwrap().remove(relay_url);
+        if let Some(conn) = slot.as_ref().and_then(|slot| slot.get()) {
+            conn.alive.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Hand an open socket to its connection task.
+    fn spawn(&self, relay_url: &str, write: WsSink, read: WsSource) -> Arc<RelayConnection> {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let conn = Arc::new(RelayConnection {
+            url: relay_url.to_string(),
+            outgoing,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            alive: Arc::new(AtomicBool::new(true)),
+        });
+        tokio::spawn(run_connection(
+            relay_url.to_string(),
+            write,
+            read,
+            outgoing_rx,
+            conn.subscriptions.clone(),
+            conn.alive.clone(),
+        ));
+
+        info!("Opened pooled connection to {}", relay_url);
+        conn
+    }
+
+    /// Open a WebSocket to a relay, through Tor when enabled.
+    async fn connect(
+        &self,
+        relay_url: &str,
+    ) -> Result<(WsSink, WsSource), Box<dyn std::error::Error + Send + Sync>> {
+        debug!("Connecting to relay: {}", relay_url);
+
+        if self.use_tor {
+            let url = url::Url::parse(relay_url)?;
+            let host = url.host_str().ok_or("Relay URL has no host")?.to_string();
+            let port = url.port().unwrap_or(80);
+            let proxy_addr = self.tor_proxy.as_deref().unwrap_or("127.0.0.1:9050");
+            info!("Connecting to {} via Tor proxy {}", relay_url, proxy_addr);
+
+            let socks_stream = Socks5Stream::connect(proxy_addr, (host.as_str(), port))
+                .await
+                .map_err(|e| format!("Failed to connect via Tor to {}: {}", relay_url, e))?;
+
+            let (ws_stream, _) = client_async_tls(relay_url, socks_stream)
+                .await
+                .map_err(|e| format!("WebSocket handshake failed for {}: {}", relay_url, e))?;
+
+            let (write, read) = ws_stream.split();
+            Ok((Box::pin(write), Box::pin(read)))
+        } else {
+            debug!("Connecting to {} directly (Tor disabled)", relay_url);
+            let (ws_stream, _) = connect_async(relay_url)
+                .await
+                .map_err(|e| format!("Failed to connect to {}: {}", relay_url, e))?;
+
+            let (write, read) = ws_stream.split();
+            Ok((Box::pin(write), Box::pin(read)))
+        }
+    }
+}
+
+/// Own the socket: write queued messages, dispatch incoming ones by `sub_id` and keep the
+/// connection alive with pings. On exit every subscription channel is dropped.
+async fn run_connection(
+    url: String,
+    mut write: WsSink,
+    mut read: WsSource,
+    mut outgoing: mpsc::UnboundedReceiver<Message>,
+    subscriptions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+    alive: Arc<AtomicBool>,
+) {
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            msg = outgoing.recv() => match msg {
+                Some(msg) => {
+                    if let Err(e) = write.send(msg).await {
+                        debug!("Write to {} failed: {}", url, e);
+                        break;
+                    }
+                }
+                // Every handle to this connection is gone
+                None => break,
+            },
+            incoming = read.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    last_seen = Instant::now();
+                    let Ok(parsed) = serde_json::from_str::<Value>(&text) else {
+                        continue;
+                    };
+                    let Some(sub_id) = parsed.get(1).and_then(|v| v.as_str()) else {
+                        continue;
+                    };
+                    let subscriptions = subscriptions.lock().unwrap();
+                    if let Some(tx) = subscriptions.get(sub_id) {
+                        let _ = tx.send(parsed.clone());
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    debug!("Relay {} closed the connection", url);
+                    break;
+                }
+                Some(Err(e)) => {
+                    debug!("WebSocket error on {}: {}", url, e);
+                    break;
+                }
+                // Pings are answered by tungstenite itself
+                Some(Ok(_)) => last_seen = Instant::now(),
+            },
+            _ = ping.tick() => {
+                if last_seen.elapsed() > IDLE_TIMEOUT {
+                    warn!("Relay {} stopped answering keepalives", url);
+                    break;
+                }
+                if write.send(Message::Ping(Vec::new().into())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    alive.store(false, Ordering::Relaxed);
+    subscriptions.lock().unwrap().clear();
+    let _ = write.close().await;
+    info!("Pooled connection to {} closed", url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+
+    /// Local relay counting the sockets it accepted. With `drop_first` the first
+    /// connection is closed right after the handshake.
+    async fn relay(drop_first: bool) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                        return;
+                    };
+                    if drop_first && n == 0 {
+                        let _ = ws.close(None).await;
+                        return;
+                    }
+                    while let Some(Ok(_)) = ws.next().await {}
+                });
+            }
+        });
+        (url, accepted)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_gets_share_one_socket() {
+        let (url, accepted) = relay(false).await;
+        let pool = RelayPool::new(false, None);
+
+        let (a, b) = tokio::join!(pool.get(&url), pool.get(&url));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(Arc::ptr_eq(&a, &pool.get(&url).await.unwrap()));
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_evict_or_drop() {
+        let (url, accepted) = relay(false).await;
+        let pool = RelayPool::new(false, None);
+        let first = pool.get(&url).await.unwrap();
+        pool.evict(&url);
+        assert!(!first.is_alive());
+        let second = pool.get(&url).await.unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        let (url, accepted) = relay(true).await;
+        let first = pool.get(&url).await.unwrap();
+        for _ in 0..50 {
+            if !first.is_alive() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!first.is_alive());
+        let second = pool.get(&url).await.unwrap();
+        assert!(second.is_alive());
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        // Accepts the TCP connection but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let mut pool = RelayPool::new(false, None);
+        pool.connect_timeout = Duration::from_millis(100);
+        let err = pool.get(&url).await.err().unwrap();
+        assert!(err.to_string().contains("timed out"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::episodes::season_episode;
+use serde::Serialize;
+
+/// Quality metadata parsed from a release name such as
+/// `Moana.2.2024.MULTi.TRUEFRENCH.1080p.WEB-DL.H265-GROUP`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq, Hash)]
+pub struct ReleaseInfo {
+    pub resolution: Option<String>,
+    pub source: Option<String>,
+    pub codec: Option<String>,
+    pub audio: Vec<String>,
+    pub languages: Vec<String>,
+    pub hdr: Vec<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub year: Option<u32>,
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Resolution,
+    Source,
+    Codec,
+    Audio,
+    Language,
+    Hdr,
+}
+
+/// Spellings joined before splitting the name on separators.
+const JOINED: &[(&str, &str)] = &[
+    ("web-dl", "webdl"),
+    ("web dl", "webdl"),
+    ("web-rip", "webrip"),
+    ("blu-ray", "bluray"),
+    ("dts-hd", "dtshd"),
+    ("dd+", "ddp"),
+    ("e-ac3", "eac3"),
+    ("h.264", "h264"),
+    ("h.265", "h265"),
+    ("hdr10+", "hdr10plus"),
+    ("dolby vision", "dovi"),
+    ("dolby.vision", "dovi"),
+];
+
+const VIDEO_EXTENSIONS: &[&str] = &[".mkv", ".mp4", ".avi", ".ts", ".m2ts"];
+
+/// Canonical value of a lowercased name token.
+pub fn canonical(token: &str) -> Option<(Field, &'static str)> {
+    let known = match token {
+        "2160p" | "4k" | "uhd" => (Field::Resolution, "2160p"),
+        "1080p" | "1080i" => (Field::Resolution, "1080p"),
+        "720p" => (Field::Resolution, "720p"),
+        "576p" => (Field::Resolution, "576p"),
+        "480p" => (Field::Resolution, "480p"),
+
+        "remux" => (Field::Source, "REMUX"),
+        "bluray" | "bdrip" | "brrip" => (Field::Source, "BluRay"),
+        "webdl" => (Field::Source, "WEB-DL"),
+        "webrip" => (Field::Source, "WEBRip"),
+        "web" => (Field::Source, "WEB"),
+        "hdlight" | "4klight" => (Field::Source, "HDLight"),
+        "hdtv" => (Field::Source, "HDTV"),
+        "hdrip" => (Field::Source, "HDRip"),
+        "dvdrip" => (Field::Source, "DVDRip"),
+        "dvd" | "dvdr" | "dvd5" | "dvd9" => (Field::Source, "DVD"),
+        "cam" | "hdcam" => (Field::Source, "CAM"),
+
+        "x264" | "h264" | "avc" => (Field::Codec, "H264"),
+        "x265" | "h265" | "hevc" => (Field::Codec, "H265"),
+        "av1" => (Field::Codec, "AV1"),
+        "xvid" | "divx" => (Field::Codec, "XviD"),
+        "vp9" => (Field::Codec, "VP9"),
+
+        "aac" => (Field::Audio, "AAC"),
+        "ac3" | "dd" => (Field::Audio, "AC3"),
+        "eac3" | "ddp" => (Field::Audio, "EAC3"),
+        "dts" => (Field::Audio, "DTS"),
+        "dtshd" => (Field::Audio, "DTS-HD"),
+        "truehd" => (Field::Audio, "TrueHD"),
+        "atmos" => (Field::Audio, "Atmos"),
+        "flac" => (Field::Audio, "FLAC"),
+        "opus" => (Field::Audio, "Opus"),
+        "mp3" => (Field::Audio, "MP3"),
+
+        "multi" => (Field::Language, "MULTi"),
+        "vff" => (Field::Language, "VFF"),
+        "vfq" => (Field::Language, "VFQ"),
+        "vfi" => (Field::Language, "VFI"),
+        "vf2" => (Field::Language, "VF2"),
+        "vf" => (Field::Language, "VF"),
+        "truefrench" => (Field::Language, "TRUEFRENCH"),
+        "french" => (Field::Language, "FRENCH"),
+        "vostfr" | "subfrench" => (Field::Language, "VOSTFR"),
+        "vo" => (Field::Language, "VO"),
+
+        "hdr" => (Field::Hdr, "HDR"),
+        "hdr10" => (Field::Hdr, "HDR10"),
+        "hdr10plus" => (Field::Hdr, "HDR10+"),
+        "dv" | "dovi" => (Field::Hdr, "DV"),
+        "hlg" => (Field::Hdr, "HLG"),
+
+        // `DDP5.1`, `AAC2.0`: the channel layout is glued to the codec
+        _ => {
+            let stripped = token.trim_end_matches(|c: char| c.is_ascii_digit());
+            return match stripped {
+                "ddp" | "dd" | "aac" | "dts" | "truehd" | "flac" | "opus" if stripped != token => {
+                    canonical(stripped)
+                }
+                _ => None,
+            };
+        }
+    };
+    Some(known)
+}
+
+impl ReleaseInfo {
+    pub fn parse(name: &str) -> Self {
+        let mut lower = name.to_lowercase();
+        for (from, to) in JOINED {
+            lower = lower.replace(from, to);
+        }
+        let tokens: Vec<&str> = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let mut info = ReleaseInfo::default();
+        for token in &tokens {
+            let Some((field, value)) = canonical(token) else {
+                continue;
+            };
+            let value = value.to_string();
+            match field {
+                Field::Resolution => {
+                    info.resolution.get_or_insert(value);
+                }
+                Field::Source => {
+                    info.source.get_or_insert(value);
+                }
+                Field::Codec => {
+                    info.codec.get_or_insert(value);
+                }
+                Field::Audio => push_unique(&mut info.audio, value),
+                Field::Language => push_unique(&mut info.languages, value),
+                Field::Hdr => push_unique(&mut info.hdr, value),
+            }
+        }
+
+        // The first token is part of the title, e.g. `1917.2019.MULTi`
+        info.year = tokens
+            .iter()
+            .skip(1)
+            .filter(|t| t.len() == 4)
+            .filter_map(|t| t.parse::<u32>().ok())
+            .rfind(|y| (1900..=2100).contains(y));
+        (info.season, info.episode) = season_episode(name);
+        info.group = release_group(name);
+        info
+    }
+}
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+/// `…x265-GROUP` → `GROUP`. The dash must follow a known tag, so `Spider-Man` or `WEB-DL`
+/// are not mistaken for a group.
+fn release_group(name: &str) -> Option<String> {
+    let mut name = name.trim();
+    for ext in VIDEO_EXTENSIONS {
+        // Names may end in a multibyte character, the suffix start can fall inside it
+        if name.len() > ext.len()
+            && name.is_char_boundary(name.len() - ext.len())
+            && name[name.len() - ext.len()..].eq_ignore_ascii_case(ext)
+        {
+            name = &name[..name.len() - ext.len()];
+        }
+    }
+    let (before, group) = name.rsplit_once('-')?;
+    let last_tag = before
+        .rsplit(|c: char| !c.is_alphanumeric())
+        .next()
+        .map(|t| t.to_lowercase())?;
+    let valid = !group.is_empty()
+        && group.len() <= 20
+        && group.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !group.eq_ignore_ascii_case("dl")
+        && canonical(&last_tag).is_some();
+    valid.then(|| group.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_movie() {
+        let info = ReleaseInfo::parse(
+            "Moana.2.2024.MULTi.TRUEFRENCH.2160p.WEB-DL.HDR10+.DV.DDP5.1.Atmos.x265-GROUP.mkv",
+        );
+        assert_eq!(info.resolution.as_deref(), Some("2160p"));
+        assert_eq!(info.source.as_deref(), Some("WEB-DL"));
+        assert_eq!(info.codec.as_deref(), Some("H265"));
+        assert_eq!(info.audio, vec!["EAC3", "Atmos"]);
+        assert_eq!(info.languages, vec!["MULTi", "TRUEFRENCH"]);
+        assert_eq!(info.hdr, vec!["HDR10+", "DV"]);
+        assert_eq!(info.year, Some(2024));
+        assert_eq!(info.group.as_deref(), Some("GROUP"));
+        assert_eq!(info.season, None);
+    }
+
+    #[test]
+    fn test_parse_episode() {
+        let info = ReleaseInfo::parse("Breaking Bad S02E05 VOSTFR 720p HDTV x264-ASAP");
+        assert_eq!((info.season, info.episode), (Some(2), Some(5)));
+        assert_eq!(info.languages, vec!["VOSTFR"]);
+        assert_eq!(info.source.as_deref(), Some("HDTV"));
+        assert_eq!(info.year, None);
+        assert_eq!(info.group.as_deref(), Some("ASAP"));
+    }
+
+    #[test]
+    fn test_no_group() {
+        assert_eq!(ReleaseInfo::parse("1917.2019.VFF.1080p.WEB-DL").group, None);
+        assert_eq!(
+            ReleaseInfo::parse("1917.2019.VFF.1080p.WEB-DL").year,
+            Some(2019)
+        );
+        assert_eq!(ReleaseInfo::parse("Spider-Man No Way Home").group, None);
+        assert_eq!(ReleaseInfo::parse("Détective Pikachu Pokémon").group, None);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::tmdb_cache::get_tmdb_cache;
+use actix_web::{HttpResponse, delete};
+
+#[delete("/admin/tmdb-cache")]
+pub async fn purge_tmdb_cache() -> HttpResponse {
+    match get_tmdb_cache() {
+        Some(cache) => {
+            let purged = cache.purge().await;
+            info!("TMDB cache purged: {} entries removed", purged);
+            HttpResponse::Ok().json(serde_json::json!({ "purged": purged }))
+        }
+        None => HttpResponse::NotFound().body("TMDB cache is disabled"),
+    }
+}
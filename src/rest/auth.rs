@@ -0,0 +1,206 @@
+use crate::auth::{Scope, find_key, required_scope};
+use crate::config::Config;
+use crate::torznab::{ERR_BAD_CREDENTIALS, ERR_NO_PRIVILEGES, error_xml};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use qstring::QString;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Reject requests without a key allowed on the route, when `api_keys` is set.
+/// Admin routes (webhooks, download clients) stay closed until an `admin` key exists.
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let config = req.app_data::<web::Data<Config>>().cloned();
+    let api_keys = config.as_ref().map_or(&[][..], |c| c.api_keys.as_slice());
+    // Routing runs on the percent-decoded path (`/%61dmin` is `/admin`), resolve the
+    // route the same way instead of trusting the raw path
+    let pattern = req.resource_map().match_pattern(req.match_info().as_str());
+    let Some(scope) = required_scope(pattern.as_deref()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if api_keys.is_empty() && scope != Scope::Admin {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    // `apikey` is the Torznab convention, the header keeps keys out of URLs
+    let presented = QString::from(req.query_string())
+        .get("apikey")
+        .map(str::to_string)
+        .or_else(|| {
+            req.headers()
+                .get(API_KEY_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        });
+    let key = presented.as_deref().and_then(|k| find_key(api_keys, k));
+    let (mut response, code, description) = match key {
+        _ if api_keys.is_empty() => {
+            warn!("No admin API key configured, refused {}", req.path());
+            (
+                HttpResponse::Forbidden(),
+                ERR_NO_PRIVILEGES,
+                "Configure an API key with the admin scope first".to_string(),
+            )
+        }
+        Some(key) if key.scopes.contains(&scope) => {
+            debug!("API key {} used for {}", key.name, req.path());
+            return Ok(next.call(req).await?.map_into_left_body());
+        }
+        Some(key) => {
+            warn!(
+                "API key {} lacks the {} scope for {}",
+                key.name,
+                scope.as_str(),
+                req.path()
+            );
+            (
+                HttpResponse::Forbidden(),
+                ERR_NO_PRIVILEGES,
+                format!("API key lacks the {} scope", scope.as_str()),
+            )
+        }
+        None => {
+            warn!("Missing or invalid API key for {}", req.path());
+            (
+                HttpResponse::Unauthorized(),
+                ERR_BAD_CREDENTIALS,
+                "Missing or invalid API key".to_string(),
+            )
+        }
+    };
+    let response = match pattern.as_deref() {
+        Some("/api") => response
+            .content_type("application/xml; charset=utf-8")
+            .body(error_xml(code, &description)),
+        _ => response.body(description),
+    };
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ApiKey;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, test};
+
+    #[actix_web::test]
+    async fn test_require_api_key() {
+        let config = Config {
+            api_keys: vec![ApiKey {
+                name: "sonarr".to_string(),
+                key: "s3cr3t".to_string(),
+                scopes: vec![Scope::Search],
+            }],
+            ..Config::default()
+        };
+        let ok = || async { HttpResponse::Ok().finish() };
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(config)).service(
+                web::scope("")
+                    .wrap(from_fn(require_api_key))
+                    .route("/health", web::get().to(ok))
+                    .route("/api", web::get().to(ok))
+                    .route("/stream", web::get().to(ok))
+                    .route("/admin/tmdb-cache", web::delete().to(ok)),
+            ),
+        )
+        .await;
+
+        let status = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+        assert_eq!(status(test::TestRequest::get().uri("/health")).await, 200);
+        assert_eq!(status(test::TestRequest::get().uri("/api")).await, 401);
+        assert_eq!(
+            status(test::TestRequest::get().uri("/api?t=caps&apikey=s3cr3t")).await,
+            200
+        );
+        assert_eq!(
+            status(
+                test::TestRequest::get()
+                    .uri("/api")
+                    .insert_header((API_KEY_HEADER, "s3cr3t"))
+            )
+            .await,
+            200
+        );
+        assert_eq!(
+            status(
+                test::TestRequest::delete()
+                    .uri("/admin/tmdb-cache")
+                    .insert_header((API_KEY_HEADER, "s3cr3t"))
+            )
+            .await,
+            403
+        );
+        // Percent-encoded paths are routed decoded and must need the same scope
+        for uri in ["/%61dmin/tmdb-cache", "/admin/tmdb-%63ache", "/%73tream"] {
+            let method = match uri.contains("tmdb") {
+                true => test::TestRequest::delete(),
+                false => test::TestRequest::get(),
+            };
+            assert_eq!(
+                status(method.uri(uri).insert_header((API_KEY_HEADER, "s3cr3t"))).await,
+                403,
+                "{}",
+                uri
+            );
+        }
+
+        let response = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/api?apikey=nope")
+                .to_request(),
+        )
+        .await;
+        let body = test::read_body(response).await;
+        assert!(String::from_utf8_lossy(&body).contains(r#"<error code="100""#));
+    }
+
+    #[actix_web::test]
+    async fn test_admin_routes_without_keys() {
+        let ok = || async { HttpResponse::Ok().finish() };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Config::default()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_api_key))
+                        .route("/api", web::get().to(ok))
+                        .route("/watch", web::post().to(ok))
+                        .route("/watch/{id}", web::delete().to(ok))
+                        .route("/torrent/{id}/send", web::post().to(ok))
+                        .route("/admin/tmdb-cache", web::delete().to(ok)),
+                ),
+        )
+        .await;
+
+        let status = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+        // Without keys everything stays open except the admin routes
+        assert_eq!(status(test::TestRequest::get().uri("/api")).await, 200);
+        assert_eq!(status(test::TestRequest::post().uri("/watch")).await, 403);
+        assert_eq!(
+            status(test::TestRequest::delete().uri("/watch/1")).await,
+            403
+        );
+        assert_eq!(
+            status(test::TestRequest::post().uri("/torrent/abc/send")).await,
+            403
+        );
+        assert_eq!(
+            status(test::TestRequest::delete().uri("/admin/tmdb-cache")).await,
+            403
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use crate::cache::get_search_cache;
 use crate::config::Config;
+use crate::index::get_local_index;
 use crate::nostr::NostrClient;
-use crate::search::{Order, Sort, search};
+use crate::search::{DEFAULT_PAGE_SIZE, Order, Sort, search};
 use actix_web::{HttpResponse, get, web};
 
 #[get("/health")]
@@ -20,11 +22,19 @@ pub async fn status_check(
         Some(Sort::Seed),
         Some(Order::Ascending),
         None,
+        DEFAULT_PAGE_SIZE,
     )
     .await;
 
     let (search_status, parsing) = match search_result {
-        Ok(torrents) => ("ok", if torrents.is_empty() { "empty" } else { "ok" }),
+        Ok(page) => (
+            "ok",
+            if page.torrents.is_empty() {
+                "empty"
+            } else {
+                "ok"
+            },
+        ),
         Err(e) => {
             error!("Status check search error: {}", e);
             ("failed", "n/a")
@@ -42,11 +52,23 @@ pub async fn status_check(
         .cloned()
         .unwrap_or_else(|| "error".to_string());
 
+    let local_index = match get_local_index() {
+        Some(index) => serde_json::json!({
+            "enabled": true,
+            "torrents": index.len(),
+            "ready": index.is_ready(),
+        }),
+        None => serde_json::json!({ "enabled": false }),
+    };
+
     let status = serde_json::json!({
         "relay": first_relay,
         "search": search_status,
         "parsing": parsing,
         "tmdb_integration": tmdb,
+        "resolvers": crate::dbs::resolver_names(),
+        "local_index": local_index,
+        "cache": get_search_cache().stats(),
     });
 
     HttpResponse::Ok().json(status)
@@ -1,21 +1,48 @@
+use crate::rest::admin::*;
+use crate::rest::auth::require_api_key;
 use crate::rest::categories::*;
 use crate::rest::homepage::*;
 use crate::rest::infos::*;
+use crate::rest::rss::*;
 use crate::rest::search::*;
+use crate::rest::stream::*;
 use crate::rest::torrent::*;
+use crate::rest::torznab::*;
+use crate::rest::watch::*;
+use actix_web::middleware::from_fn;
 use actix_web::web;
 
+mod admin;
+mod auth;
 mod categories;
 mod homepage;
 mod infos;
+mod rss;
 pub mod search;
+mod stream;
 mod torrent;
+mod torznab;
+mod watch;
 
 pub fn config_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(categories)
-        .service(ygg_search)
-        .service(download_torrent)
-        .service(health_check)
-        .service(status_check)
-        .service(index);
+    cfg.service(
+        web::scope("")
+            .wrap(from_fn(require_api_key))
+            .service(categories)
+            .service(ygg_search)
+            .service(torznab_api)
+            .service(rss_feed)
+            .service(rss_category_feed)
+            .service(live_stream)
+            .service(list_watch_rules)
+            .service(add_watch_rule)
+            .service(delete_watch_rule)
+            .service(download_torrent)
+            .service(torrent_details)
+            .service(send_torrent)
+            .service(health_check)
+            .service(status_check)
+            .service(purge_tmdb_cache)
+            .service(index),
+    );
 }
@@ -0,0 +1,153 @@
+use crate::ban_words::BanWords;
+use crate::categories::CATEGORIES_CACHE;
+use crate::config::Config;
+use crate::feed::{Enclosure, Feed, FeedFormat};
+use crate::filters::SearchFilter;
+use crate::nostr::NostrClient;
+use crate::rest::torznab::base_url;
+use crate::search::{Order, Page, Sort, search_page};
+use crate::torznab::{MAX_RESULTS, rfc2822_date};
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, get, web};
+use qstring::QString;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Number of items of a feed without `limit`.
+const DEFAULT_FEED_SIZE: usize = 50;
+
+#[get("/rss")]
+pub async fn rss_feed(
+    nostr: web::Data<NostrClient>,
+    config: web::Data<Config>,
+    req_data: HttpRequest,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    feed_response(&nostr, &config, &req_data, None).await
+}
+
+#[get("/rss/{category}")]
+pub async fn rss_category_feed(
+    nostr: web::Data<NostrClient>,
+    config: web::Data<Config>,
+    req_data: HttpRequest,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let category = req_data
+        .match_info()
+        .get("category")
+        .and_then(|c| c.parse::<usize>().ok());
+    match category.filter(|c| category_name(*c).is_some()) {
+        Some(category) => feed_response(&nostr, &config, &req_data, Some(category)).await,
+        None => Ok(HttpResponse::NotFound().body("Unknown category")),
+    }
+}
+
+/// Latest uploads as RSS 2.0 or Atom, answering `304 Not Modified` to conditional requests.
+async fn feed_response(
+    nostr: &NostrClient,
+    config: &Config,
+    req_data: &HttpRequest,
+    category: Option<usize>,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let qs = QString::from(req_data.query_string());
+    let format = match qs.get("format").unwrap_or("rss").parse::<FeedFormat>() {
+        Ok(format) => format,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    // `.torrent` files can only be served from a torrent cache
+    let default_enclosure = match config.torrent_cache_urls.is_empty() {
+        true => "magnet",
+        false => "torrent",
+    };
+    let enclosure = match qs
+        .get("enclosure")
+        .unwrap_or(default_enclosure)
+        .parse::<Enclosure>()
+    {
+        Ok(enclosure) => enclosure,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let ban_words = match BanWords::from_query(&qs) {
+        Ok(ban_words) => ban_words,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let limit = qs
+        .get("limit")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_FEED_SIZE)
+        .clamp(1, MAX_RESULTS);
+
+    let page = Page {
+        limit,
+        ..Page::default()
+    };
+    let torrents = search_page(
+        nostr,
+        "",
+        category,
+        Some(Sort::PublishDate),
+        Some(Order::Descending),
+        ban_words,
+        &SearchFilter::from_query(&qs),
+        &page,
+    )
+    .await
+    .map_err(|e| format!("{}", e))?
+    .torrents;
+
+    let base_url = base_url(req_data);
+    let feed = Feed {
+        title: match category.and_then(category_name) {
+            Some(name) => format!("Ygégé - {}", name),
+            None => "Ygégé".to_string(),
+        },
+        self_url: format!("{}{}", base_url, req_data.uri()),
+        base_url: &base_url,
+        enclosure,
+        apikey: qs.get("apikey"),
+        torrents: &torrents,
+    };
+
+    let etag = feed.etag(format);
+    let last_modified = feed.last_modified().unwrap_or(0);
+    // If-None-Match takes precedence over If-Modified-Since (RFC 9110)
+    let not_modified = match req_data.headers().get(header::IF_NONE_MATCH) {
+        Some(value) => value.to_str().is_ok_and(|v| {
+            v.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == etag)
+        }),
+        None => req_data
+            .headers()
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .is_some_and(|since| UNIX_EPOCH + Duration::from_secs(last_modified) <= since),
+    };
+
+    let mut response = match not_modified {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::LAST_MODIFIED, rfc2822_date(last_modified)));
+    if not_modified {
+        return Ok(response.finish());
+    }
+    info!("RSS feed: {} torrents", torrents.len());
+    Ok(response
+        .content_type(format.content_type())
+        .body(feed.to_xml(format)))
+}
+
+/// `Film/Vidéo` or `Film/Vidéo Film` for a sub-category.
+fn category_name(id: usize) -> Option<String> {
+    CATEGORIES_CACHE.get()?.iter().find_map(|cat| {
+        if cat.id == id {
+            return Some(cat.name.clone());
+        }
+        cat.sub_categories
+            .iter()
+            .find(|sub| sub.id == id)
+            .map(|sub| format!("{} {}", cat.name, sub.name))
+    })
+}
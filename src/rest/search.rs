@@ -1,22 +1,41 @@
+use crate::ban_words::BanWords;
 use crate::config::Config;
 use crate::dbs::DbQueryType::*;
+use crate::episodes::{episode_queries, matches_episode, parse_number_param};
+use crate::filters::SearchFilter;
 use crate::nostr::NostrClient;
-use crate::parser::Torrent;
-use crate::search::{Order, Sort, search};
+use crate::parser::{ExternalId, Torrent};
+use crate::ranking::{Ranking, find_profile};
+use crate::search::{
+    Cursor, MAX_PAGE_SIZE, Order, Page, Sort, external_id_search, quote_phrase, relaxed_queries,
+    search, search_page, token_coverage, tokenize,
+};
 use actix_web::{HttpRequest, HttpResponse, get, web};
 use futures::future::join_all;
 use qstring::QString;
 use serde_json::Value;
 use std::collections::HashSet;
 
-async fn batch_best_search(
+/// Response header carrying the `offset` of the next page.
+const NEXT_OFFSET_HEADER: &str = "X-Next-Offset";
+/// Response header carrying the `cursor` of the next page.
+const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+/// Results scored with `sort=relevance`: the most recent matches, whatever the page.
+const RELEVANCE_CANDIDATES: usize = MAX_PAGE_SIZE;
+
+/// Search every query in parallel, `limit` results each. Queries are given most likely
+/// first: the first one returning more than 5 torrents wins, otherwise all the results are
+/// merged. Also returns whether a query had more results than `limit`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn batch_best_search(
     nostr: &NostrClient,
     queries: Vec<String>,
     category: Option<usize>,
     sort: Option<Sort>,
     order: Option<Order>,
-    ban_words: Option<Vec<String>>,
-) -> Result<Vec<Torrent>, Box<dyn std::error::Error + Send + Sync>> {
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<(Vec<Torrent>, bool), Box<dyn std::error::Error + Send + Sync>> {
     debug!("Starting parallel search for {} queries", queries.len());
 
     let search_futures: Vec<_> = queries
@@ -29,6 +48,7 @@ async fn batch_best_search(
                 sort,
                 order,
                 ban_words.clone(),
+                limit,
             )
         })
         .collect();
@@ -36,10 +56,12 @@ async fn batch_best_search(
     let results = join_all(search_futures).await;
 
     let mut collected_torrents: HashSet<Torrent> = HashSet::new();
+    let mut truncated = false;
 
     for (idx, result) in results.into_iter().enumerate() {
         match result {
-            Ok(mut torrents) => {
+            Ok(page) => {
+                let mut torrents = page.torrents;
                 if torrents.len() > 5 {
                     debug!(
                         "Found {} torrents for query #{} - returning immediately",
@@ -47,8 +69,9 @@ async fn batch_best_search(
                         idx + 1,
                     );
                     Torrent::sort(&mut torrents, sort, order);
-                    return Ok(torrents);
+                    return Ok((torrents, page.next_offset.is_some()));
                 } else {
+                    truncated |= page.next_offset.is_some();
                     torrents.into_iter().for_each(|t| {
                         collected_torrents.insert(t);
                     });
@@ -62,17 +85,18 @@ async fn batch_best_search(
 
     let mut torrents: Vec<Torrent> = collected_torrents.into_iter().collect();
     Torrent::sort(&mut torrents, sort, order);
-    Ok(torrents)
+    Ok((torrents, truncated))
 }
 
-async fn batch_category_search(
+pub(crate) async fn batch_category_search(
     nostr: &NostrClient,
     name: &str,
     cats_list: Vec<usize>,
     sort: Option<Sort>,
     order: Option<Order>,
-    ban_words: Option<Vec<String>>,
-) -> Result<Vec<Torrent>, Box<dyn std::error::Error + Send + Sync>> {
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<(Vec<Torrent>, bool), Box<dyn std::error::Error + Send + Sync>> {
     debug!(
         "Starting parallel search across {} categories",
         cats_list.len()
@@ -80,22 +104,34 @@ async fn batch_category_search(
 
     let search_futures: Vec<_> = cats_list
         .iter()
-        .map(|cat| search(nostr, name, Some(*cat), sort, order, ban_words.clone()))
+        .map(|cat| {
+            search(
+                nostr,
+                name,
+                Some(*cat),
+                sort,
+                order,
+                ban_words.clone(),
+                limit,
+            )
+        })
         .collect();
 
     let results = join_all(search_futures).await;
 
     let mut collected_torrents: HashSet<Torrent> = HashSet::new();
+    let mut truncated = false;
 
     for (idx, result) in results.into_iter().enumerate() {
         match result {
-            Ok(torrents) => {
+            Ok(page) => {
                 debug!(
                     "Category {} returned {} results",
                     cats_list[idx],
-                    torrents.len()
+                    page.torrents.len()
                 );
-                torrents.into_iter().for_each(|t| {
+                truncated |= page.next_offset.is_some();
+                page.torrents.into_iter().for_each(|t| {
                     collected_torrents.insert(t);
                 });
             }
@@ -107,7 +143,251 @@ async fn batch_category_search(
 
     let mut torrents: Vec<Torrent> = collected_torrents.into_iter().collect();
     Torrent::sort(&mut torrents, sort, order);
-    Ok(torrents)
+    Ok((torrents, truncated))
+}
+
+/// Search every season/episode query variant in parallel (across each category if given),
+/// then keep only the releases matching the requested season and episode.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn episode_search(
+    nostr: &NostrClient,
+    name: &str,
+    season: u32,
+    episode: Option<u32>,
+    cats_list: Option<Vec<usize>>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<(Vec<Torrent>, bool), Box<dyn std::error::Error + Send + Sync>> {
+    let queries = episode_queries(name, season, episode);
+    let cats: Vec<Option<usize>> = match cats_list {
+        Some(cats) => cats.into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+    debug!(
+        "Starting season {} episode {:?} search for {} queries across {} categories",
+        season,
+        episode,
+        queries.len(),
+        cats.len()
+    );
+
+    let search_futures: Vec<_> = queries
+        .iter()
+        .flat_map(|query| cats.iter().map(move |cat| (query, *cat)))
+        .map(|(query, cat)| search(nostr, query, cat, sort, order, ban_words.clone(), limit))
+        .collect();
+
+    let results = join_all(search_futures).await;
+
+    let mut collected_torrents: HashSet<Torrent> = HashSet::new();
+    let mut truncated = false;
+
+    for (idx, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(page) => {
+                truncated |= page.next_offset.is_some();
+                page.torrents.into_iter().for_each(|t| {
+                    collected_torrents.insert(t);
+                });
+            }
+            Err(e) => {
+                warn!("Episode search failed for query #{}: {}", idx + 1, e);
+            }
+        }
+    }
+
+    let mut torrents: Vec<Torrent> = collected_torrents
+        .into_iter()
+        .filter(|t| matches_episode(&t.name, season, episode))
+        .collect();
+    Torrent::sort(&mut torrents, sort, order);
+    Ok((torrents, truncated))
+}
+
+/// Season/episode search for a show known under several titles: the titles are tried in
+/// order and the first one with matching releases wins.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn titles_episode_search(
+    nostr: &NostrClient,
+    titles: Vec<String>,
+    season: u32,
+    episode: Option<u32>,
+    cats_list: Option<Vec<usize>>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<(Vec<Torrent>, bool), Box<dyn std::error::Error + Send + Sync>> {
+    let mut last_error = None;
+    let mut any_answered = false;
+    for title in &titles {
+        match episode_search(
+            nostr,
+            title,
+            season,
+            episode,
+            cats_list.clone(),
+            sort,
+            order,
+            ban_words.clone(),
+            limit,
+        )
+        .await
+        {
+            Ok((torrents, truncated)) if !torrents.is_empty() => {
+                debug!("Found {} torrents for title {:?}", torrents.len(), title);
+                return Ok((torrents, truncated));
+            }
+            Ok(_) => any_answered = true,
+            Err(e) => {
+                warn!("Episode search failed for title {:?}: {}", title, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        // Every title failed, most likely the relays are unreachable
+        Some(e) if !any_answered => Err(e),
+        _ => Ok((Vec::new(), false)),
+    }
+}
+
+/// The `i` tag references matching the `imdbid`/`tmdbid` parameters.
+pub(crate) fn requested_external_ids(
+    imdbid: Option<&str>,
+    tmdbid: Option<&str>,
+    tv: bool,
+) -> Vec<ExternalId> {
+    let mut ids = Vec::new();
+    if let Some(id) = imdbid {
+        ids.push(ExternalId::imdb(id));
+    }
+    if let Some(id) = tmdbid {
+        ids.push(ExternalId::tmdb(id, tv));
+    }
+    ids
+}
+
+/// Exact search on the NIP-35 `i` tags, across each category if given, restricted to a
+/// season (and episode) if given. Failures are logged and treated as no match, so callers
+/// fall back to title searches. Also returns whether there were more than `limit` tagged
+/// torrents.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn tagged_search(
+    nostr: &NostrClient,
+    external_ids: &[ExternalId],
+    cats_list: Option<Vec<usize>>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> (Vec<Torrent>, bool) {
+    if external_ids.is_empty() {
+        return (Vec::new(), false);
+    }
+    let cats: Vec<Option<usize>> = match cats_list {
+        Some(cats) => cats.into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+    let search_futures: Vec<_> = cats
+        .iter()
+        .map(|cat| {
+            external_id_search(
+                nostr,
+                external_ids,
+                *cat,
+                sort,
+                order,
+                ban_words.clone(),
+                limit,
+            )
+        })
+        .collect();
+
+    let mut collected_torrents: HashSet<Torrent> = HashSet::new();
+    let mut truncated = false;
+    for (cat, result) in cats.iter().zip(join_all(search_futures).await) {
+        match result {
+            Ok(torrents) => {
+                truncated |= torrents.len() >= limit;
+                collected_torrents.extend(torrents);
+            }
+            Err(e) => warn!(
+                "External ID search failed for {:?} in category {:?}: {}",
+                external_ids, cat, e
+            ),
+        }
+    }
+
+    let mut torrents: Vec<Torrent> = collected_torrents
+        .into_iter()
+        .filter(|t| season.is_none_or(|season| matches_episode(&t.name, season, episode)))
+        .collect();
+    Torrent::sort(&mut torrents, sort, order);
+    debug!("{} torrents tagged with {:?}", torrents.len(), external_ids);
+    (torrents, truncated)
+}
+
+/// Extended search mode: run relaxed variants of the query in parallel (across each category
+/// if given), merge the results and rank them by how many query tokens they contain.
+pub(crate) async fn extended_search(
+    nostr: &NostrClient,
+    name: &str,
+    cats_list: Option<Vec<usize>>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<(Vec<Torrent>, bool), Box<dyn std::error::Error + Send + Sync>> {
+    let queries = relaxed_queries(name);
+    let cats: Vec<Option<usize>> = match cats_list {
+        Some(cats) => cats.into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+    debug!(
+        "Starting extended search for {} relaxed queries: {:?}",
+        queries.len(),
+        queries
+    );
+
+    let search_futures: Vec<_> = queries
+        .iter()
+        .flat_map(|query| cats.iter().map(move |cat| (query, *cat)))
+        .map(|(query, cat)| search(nostr, query, cat, sort, order, ban_words.clone(), limit))
+        .collect();
+
+    let results = join_all(search_futures).await;
+
+    let mut collected_torrents: HashSet<Torrent> = HashSet::new();
+    let mut truncated = false;
+
+    for (idx, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(page) => {
+                truncated |= page.next_offset.is_some();
+                page.torrents.into_iter().for_each(|t| {
+                    collected_torrents.insert(t);
+                });
+            }
+            Err(e) => {
+                warn!("Extended search failed for query #{}: {}", idx + 1, e);
+            }
+        }
+    }
+
+    let query_tokens = tokenize(name);
+    let mut torrents: Vec<Torrent> = collected_torrents
+        .into_iter()
+        .filter(|t| token_coverage(&query_tokens, &t.name) > 0)
+        .collect();
+    Torrent::sort(&mut torrents, sort, order);
+    // Stable sort: the requested order is kept among results with the same coverage
+    torrents.sort_by_key(|t| std::cmp::Reverse(token_coverage(&query_tokens, &t.name)));
+    Ok((torrents, truncated))
 }
 
 #[get("/search")]
@@ -120,24 +400,54 @@ pub async fn ygg_search(
     debug!("Received query: {}", query);
     let qs = QString::from(query);
     let name = qs.get("name").or(qs.get("q")).unwrap_or("");
+    let quote_search = qs.get("quote_search").and_then(|s| s.parse::<bool>().ok());
+    // quote_search=false asks for exact-phrase matching of a plain name search,
+    // quote_search=true for the extended mode
+    let plain_name = match quote_search {
+        Some(false) => quote_phrase(name),
+        _ => name.to_string(),
+    };
     let category = qs.get("category").and_then(|s| s.parse::<usize>().ok());
     let mut sort = qs.get("sort").and_then(|s| s.parse::<Sort>().ok());
     let mut order = qs.get("order").and_then(|s| s.parse::<Order>().ok());
     let cats = qs.get("categories");
     let connarr = qs.get("connarr");
+    let season = qs.get("season").and_then(parse_number_param);
+    let episode = qs.get("ep").and_then(parse_number_param);
+    let page = match parse_page(&qs) {
+        Ok(page) => page,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let filter = SearchFilter::from_query(&qs);
+    // Relevance is scored on the merged results, the searches keep their default order
+    let ranking = match sort {
+        Some(Sort::Relevance) => {
+            let profile = qs.get("profile").filter(|p| !p.is_empty());
+            match find_profile(&config, profile) {
+                Ok(profile) => Some(Ranking::new(profile, name, order.take())),
+                Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+            }
+        }
+        _ => None,
+    };
+    if ranking.is_some() {
+        sort = None;
+    }
+    let ranking = ranking.as_ref();
+    // Every query fetches up to the end of the page, or the whole candidate set to rank
+    let wanted = match ranking {
+        Some(_) => RELEVANCE_CANDIDATES,
+        None => page.end(),
+    };
 
     if connarr.is_some() && category.is_some() {
         debug!("Prowlarr/Jackett detected");
     }
 
-    let ban_words = qs.get("ban_words").and_then(|s| {
-        let v: Vec<String> = s
-            .split(',')
-            .map(|word| word.trim().to_string())
-            .filter(|w| !w.is_empty())
-            .collect();
-        if v.is_empty() { None } else { Some(v) }
-    });
+    let ban_words = match BanWords::from_query(&qs) {
+        Ok(ban_words) => ban_words,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
 
     let mut categories_list = if let Some(cats) = cats {
         let decoded = urlencoding::decode(cats).unwrap_or(std::borrow::Cow::Borrowed(cats));
@@ -154,54 +464,83 @@ pub async fn ygg_search(
         None
     };
 
-    if categories_list.is_some() && connarr.is_some() {
-        if categories_list.as_ref().unwrap().len() > 2 {
-            categories_list = None;
-        }
+    if connarr.is_some() && categories_list.as_ref().is_some_and(|c| c.len() > 2) {
+        categories_list = None;
     }
 
-    // TMDB/IMDB lookup
-    if config.tmdb_token.is_some() && (qs.get("tmdbid").is_some() || qs.get("imdbid").is_some()) {
-        let db_search = if let Some(id) = qs.get("tmdbid") {
-            Some((id, TMDB, "TMDB"))
-        } else if let Some(id) = qs.get("imdbid") {
-            Some((id, IMDB, "IMDB"))
-        } else {
-            None
-        };
+    // TMDB/IMDB/TVDB lookup
+    let db_param = |key: &str| qs.get(key).filter(|id| !id.is_empty());
+    let has_db_id = ["tmdbid", "imdbid", "tvdbid"]
+        .iter()
+        .any(|key| db_param(key).is_some());
+    // Events tagged with the requested IDs are exact matches, titles are only a fallback
+    let external_ids =
+        requested_external_ids(db_param("imdbid"), db_param("tmdbid"), season.is_some());
+    let tagged = tagged_search(
+        &nostr,
+        &external_ids,
+        category.map(|c| vec![c]).or(categories_list.clone()),
+        season,
+        episode,
+        sort,
+        order,
+        ban_words.clone(),
+        wanted,
+    )
+    .await;
+    if !tagged.0.is_empty() {
+        info!("{} torrents found via external ID tags", tagged.0.len());
+        return Ok(paged_response(tagged, &page, &filter, ranking));
+    }
 
-        if let Some((id, db_type, db_name)) = db_search {
-            match crate::dbs::get_queries(
-                id.to_string(),
-                &config.tmdb_token.clone().unwrap(),
-                db_type,
-            )
-            .await
-            {
-                Ok(queries) => {
-                    debug!("Found database query for {} queries", queries.len());
+    // With a season, a TMDB ID refers to a TV show, otherwise it is tried as a movie first
+    let tmdb_type = match season {
+        Some(_) => (TMDBTV, "TMDB TV"),
+        None => (TMDB, "TMDB"),
+    };
+    let db_search = [
+        ("tmdbid", tmdb_type.0, tmdb_type.1),
+        ("tvdbid", TVDB, "TVDB"),
+        ("imdbid", IMDB, "IMDB"),
+    ]
+    .into_iter()
+    .filter(|(_, db_type, _)| crate::dbs::can_resolve(*db_type))
+    .find_map(|(key, db_type, db_name)| db_param(key).map(|id| (id, db_type, db_name)));
 
-                    let results =
-                        batch_best_search(&nostr, queries, category, sort, order, ban_words)
-                            .await
-                            .map_err(|e| format!("{}", e))?;
+    if let Some((id, db_type, db_name)) = db_search {
+        match crate::dbs::get_queries(id.to_string(), &config, db_type).await {
+            Ok(queries) => {
+                debug!("Found database query for {} queries", queries.len());
 
-                    if !results.is_empty() {
-                        info!("{} torrents found via {} search", results.len(), db_name);
-                        let json: Vec<Value> = results.into_iter().map(|t| t.to_json()).collect();
-                        return Ok(HttpResponse::Ok().json(json));
+                let results = match season {
+                    Some(season) => {
+                        let cats = category.map(|c| vec![c]).or(categories_list);
+                        titles_episode_search(
+                            &nostr, queries, season, episode, cats, sort, order, ban_words, wanted,
+                        )
+                        .await
+                    }
+                    None => {
+                        batch_best_search(&nostr, queries, category, sort, order, ban_words, wanted)
+                            .await
                     }
-                    return Ok(HttpResponse::Ok().json(Vec::<Value>::new()));
                 }
-                Err(e) => {
-                    warn!("Failed to get {} queries for ID {}: {}", db_name, id, e);
-                    return Ok(HttpResponse::Ok().json(Vec::<Value>::new()));
+                .map_err(|e| format!("{}", e))?;
+
+                if !results.0.is_empty() {
+                    info!("{} torrents found via {} search", results.0.len(), db_name);
+                    return Ok(paged_response(results, &page, &filter, ranking));
                 }
+                return Ok(HttpResponse::Ok().json(Vec::<Value>::new()));
+            }
+            Err(e) => {
+                warn!("Failed to get {} queries for ID {}: {}", db_name, id, e);
+                return Ok(HttpResponse::Ok().json(Vec::<Value>::new()));
             }
         }
-    } else if (qs.get("tmdbid").is_some() || qs.get("imdbid").is_some()) && name.is_empty() {
+    } else if has_db_id && name.is_empty() {
         warn!(
-            "Database ID provided but no TMDB token configured and no name query - returning empty result"
+            "Database ID provided but no resolver can handle it and no name query - returning empty result"
         );
         return Ok(HttpResponse::Ok().json(Vec::<Value>::new()));
     }
@@ -212,22 +551,191 @@ pub async fn ygg_search(
         sort = Some(Sort::PublishDate);
     }
 
-    // Bulk category search
-    if category.is_none() && categories_list.is_some() {
-        let cats = categories_list.unwrap();
-        let results = batch_category_search(&nostr, name, cats, sort, order, ban_words)
+    // TV search: expand the query for the season/episode and filter strictly on the release name
+    if let Some(season) = season {
+        let cats = category.map(|c| vec![c]).or(categories_list);
+        let results = episode_search(
+            &nostr, name, season, episode, cats, sort, order, ban_words, wanted,
+        )
+        .await
+        .map_err(|e| format!("{}", e))?;
+        info!(
+            "{} torrents found for season {} episode {:?}",
+            results.0.len(),
+            season,
+            episode
+        );
+        return Ok(paged_response(results, &page, &filter, ranking));
+    }
+
+    // Extended search: relaxed query variants ranked by token coverage
+    if quote_search == Some(true) && !name.is_empty() {
+        let cats = category.map(|c| vec![c]).or(categories_list);
+        let results = extended_search(&nostr, name, cats, sort, order, ban_words, wanted)
             .await
             .map_err(|e| format!("{}", e))?;
-        info!("{} torrents found via bulk category search", results.len());
-        let json: Vec<Value> = results.into_iter().map(|t| t.to_json()).collect();
-        return Ok(HttpResponse::Ok().json(json));
+        info!("{} torrents found via extended search", results.0.len());
+        return Ok(paged_response(results, &page, &filter, ranking));
     }
 
-    let torrents = search(&nostr, name, category, sort, order, ban_words)
+    // Bulk category search
+    if let (None, Some(cats)) = (category, categories_list) {
+        let results =
+            batch_category_search(&nostr, &plain_name, cats, sort, order, ban_words, wanted)
+                .await
+                .map_err(|e| format!("{}", e))?;
+        info!(
+            "{} torrents found via bulk category search",
+            results.0.len()
+        );
+        return Ok(paged_response(results, &page, &filter, ranking));
+    }
+
+    // Rank the candidates before cutting the page, so pages of a ranking never overlap
+    if let Some(ranking) = ranking {
+        let candidates = Page {
+            offset: 0,
+            limit: RELEVANCE_CANDIDATES,
+            cursor: None,
+            ..page.clone()
+        };
+        let results = search_page(
+            &nostr,
+            &plain_name,
+            category,
+            sort,
+            order,
+            ban_words,
+            &filter,
+            &candidates,
+        )
         .await
         .map_err(|e| format!("{}", e))?;
+        info!("{} torrents found", results.torrents.len());
+        return Ok(paged_response(
+            (results.torrents, false),
+            &page,
+            &filter,
+            Some(ranking),
+        ));
+    }
+
+    let results = search_page(
+        &nostr,
+        &plain_name,
+        category,
+        sort,
+        order,
+        ban_words,
+        &filter,
+        &page,
+    )
+    .await
+    .map_err(|e| format!("{}", e))?;
+
+    info!("{} torrents found", results.torrents.len());
+    Ok(json_response(
+        &results.torrents,
+        results.next_offset,
+        results.next_cursor,
+    ))
+}
+
+/// Read `offset`, `limit`, `since`, `until` and `cursor` from the query string.
+fn parse_page(qs: &QString) -> Result<Page, String> {
+    let default = Page::default();
+    Ok(Page {
+        offset: qs
+            .get("offset")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default.offset),
+        limit: qs
+            .get("limit")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(default.limit)
+            .clamp(1, MAX_PAGE_SIZE),
+        since: qs.get("since").and_then(|s| s.parse().ok()),
+        until: qs.get("until").and_then(|s| s.parse().ok()),
+        cursor: qs
+            .get("cursor")
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .transpose()?,
+    })
+}
+
+/// Filter the merged results of a multi-query search and cut them to the requested page.
+/// The date bounds are applied on the publication date since the results are already fetched.
+/// `results` carries whether a query had more results than it fetched, a ranking never goes
+/// past its candidates.
+fn paged_response(
+    results: (Vec<Torrent>, bool),
+    page: &Page,
+    filter: &SearchFilter,
+    ranking: Option<&Ranking>,
+) -> HttpResponse {
+    let (results, truncated) = results;
+    let (torrents, next_offset) = ranked_page(results, page, filter, ranking);
+    // The results are a prefix of a longer list, the next page starts after this one
+    let more = truncated && ranking.is_none() && !torrents.is_empty();
+    let next_offset = next_offset.or(more.then(|| page.offset + torrents.len()));
+    json_response(&torrents, next_offset, None)
+}
+
+/// Filter, rank with `sort=relevance`, and cut the results to the requested page.
+fn ranked_page(
+    mut results: Vec<Torrent>,
+    page: &Page,
+    filter: &SearchFilter,
+    ranking: Option<&Ranking>,
+) -> (Vec<Torrent>, Option<usize>) {
+    filter.apply(&mut results);
+    results.retain(|t| {
+        let date = t.age_stamp as u64;
+        page.since.is_none_or(|s| date >= s) && page.until.is_none_or(|u| date <= u)
+    });
+    if let Some(ranking) = ranking {
+        ranking.rank(&mut results);
+    }
+    page.slice(results)
+}
 
+/// JSON array of torrents, with the next page offset in the `X-Next-Offset` header and its
+/// cursor in `X-Next-Cursor`.
+fn json_response(
+    torrents: &[Torrent],
+    next_offset: Option<usize>,
+    next_cursor: Option<Cursor>,
+) -> HttpResponse {
     let json: Vec<Value> = torrents.iter().map(|t| t.to_json()).collect();
-    info!("{} torrents found", json.len());
-    Ok(HttpResponse::Ok().json(json))
+    let mut response = HttpResponse::Ok();
+    if let Some(next_offset) = next_offset {
+        response.insert_header((NEXT_OFFSET_HEADER, next_offset.to_string()));
+    }
+    if let Some(next_cursor) = next_cursor {
+        response.insert_header((NEXT_CURSOR_HEADER, next_cursor.to_string()));
+    }
+    response.json(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_external_ids() {
+        let ids = |imdbid, tmdbid, tv| {
+            requested_external_ids(imdbid, tmdbid, tv)
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(Some("1160419"), Some("438631"), false),
+            vec!["imdb:tt1160419", "tmdb:movie:438631"]
+        );
+        assert_eq!(ids(Some("TT0903747"), None, true), vec!["imdb:tt0903747"]);
+        assert_eq!(ids(None, Some(" 1396 "), true), vec!["tmdb:tv:1396"]);
+        assert!(ids(None, None, true).is_empty());
+    }
 }
@@ -0,0 +1,63 @@
+use crate::ban_words::BanWords;
+use crate::filters::SearchFilter;
+use crate::stream::{StreamFilter, sse_event, subscribe_live};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, get};
+use futures::StreamExt;
+use qstring::QString;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Comment sent on idle connections so proxies do not close them.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(20);
+
+/// New uploads as Server-Sent Events, filtered with the `/search` parameters.
+#[get("/stream")]
+pub async fn live_stream(req_data: HttpRequest) -> HttpResponse {
+    let qs = QString::from(req_data.query_string());
+    let ban_words = match BanWords::from_query(&qs) {
+        Ok(ban_words) => ban_words,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let categories: Vec<usize> = qs
+        .get("category")
+        .into_iter()
+        .chain(qs.get("categories"))
+        .flat_map(|value| value.split(','))
+        .filter_map(|c| c.trim().parse().ok())
+        .collect();
+    let query = qs.get("name").or(qs.get("q")).unwrap_or("");
+    let filter = StreamFilter::new(categories, query, ban_words, SearchFilter::from_query(&qs));
+
+    let Some(receiver) = subscribe_live() else {
+        return HttpResponse::ServiceUnavailable().body("Live stream not initialized");
+    };
+    info!("Live stream client connected");
+
+    let events = futures::stream::unfold((receiver, filter), |(mut receiver, filter)| async {
+        loop {
+            let chunk = match tokio::time::timeout(KEEPALIVE_INTERVAL, receiver.recv()).await {
+                Err(_) => ": keepalive\n\n".to_string(),
+                Ok(Ok(torrent)) if filter.matches(&torrent) => sse_event(&torrent),
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(missed))) => {
+                    warn!("Live stream client too slow, {} torrents skipped", missed);
+                    continue;
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((
+                Ok::<_, actix_web::Error>(Bytes::from(chunk)),
+                (receiver, filter),
+            ));
+        }
+    });
+    // Ask EventSource clients to wait before reconnecting
+    let retry = futures::stream::once(async { Ok(Bytes::from_static(b"retry: 10000\n\n")) });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(retry.chain(events))
+}
@@ -1,22 +1,125 @@
-use crate::nostr::{NostrClient, magnet_from_event};
-use actix_web::{HttpRequest, HttpResponse, get, web};
+use crate::config::Config;
+use crate::nostr::{NostrClient, parse_nip35_details};
+use crate::torrent_file::{build_torrent, resolve_info};
+use actix_web::{HttpRequest, HttpResponse, get, post, web};
+use qstring::QString;
 
 #[get("/torrent/{id}")]
 pub async fn download_torrent(
     nostr: web::Data<NostrClient>,
+    config: web::Data<Config>,
     req_data: HttpRequest,
 ) -> Result<HttpResponse, Box<dyn std::error::Error>> {
     let id = req_data.match_info().get("id").unwrap_or("");
+    let qs = QString::from(req_data.query_string());
+    let want_file = qs.get("format") == Some("torrent");
 
     let event = nostr
         .get_event(id)
         .await
         .map_err(|e| format!("Relay error: {}", e))?;
 
-    match event.and_then(|e| magnet_from_event(&e)) {
-        Some(magnet) => Ok(HttpResponse::Found()
-            .insert_header(("Location", magnet))
-            .finish()),
+    let Some(details) = event.as_ref().and_then(parse_nip35_details) else {
+        return Ok(HttpResponse::NotFound().body("Torrent not found"));
+    };
+
+    if want_file {
+        if config.torrent_cache_urls.is_empty() {
+            return Ok(HttpResponse::NotFound().body(
+                "No torrent cache configured, set torrent_cache_urls to serve .torrent files",
+            ));
+        }
+        let Some(info) = resolve_info(&details.torrent.infohash, &config.torrent_cache_urls).await
+        else {
+            warn!(
+                "Could not resolve metadata of {} from the torrent caches",
+                details.torrent.infohash
+            );
+            return Ok(HttpResponse::BadGateway()
+                .body("Torrent metadata not found in the configured torrent caches"));
+        };
+        let filename = details.torrent.name.replace(['"', '/', '\\'], "_");
+        return Ok(HttpResponse::Ok()
+            .content_type("application/x-bittorrent")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.torrent\"", filename),
+            ))
+            .body(build_torrent(info, &details)));
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header(("Location", details.torrent.magnet))
+        .finish())
+}
+
+/// Add the magnet of a torrent to a configured download client.
+#[post("/torrent/{id}/send")]
+pub async fn send_torrent(
+    nostr: web::Data<NostrClient>,
+    config: web::Data<Config>,
+    req_data: HttpRequest,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let id = req_data.match_info().get("id").unwrap_or("");
+    let qs = QString::from(req_data.query_string());
+
+    // `client` may be omitted when a single one is configured
+    let client = match qs.get("client") {
+        Some(name) => config.download_clients.get_key_value(name),
+        None if config.download_clients.len() == 1 => config.download_clients.iter().next(),
+        None => None,
+    };
+    let Some((client_name, client)) = client else {
+        let names: Vec<&str> = config.download_clients.keys().map(String::as_str).collect();
+        return Ok(HttpResponse::BadRequest().body(match names.is_empty() {
+            true => "No download client configured".to_string(),
+            false => format!("client must be one of {}", names.join(", ")),
+        }));
+    };
+
+    let event = nostr
+        .get_event(id)
+        .await
+        .map_err(|e| format!("Relay error: {}", e))?;
+
+    let Some(details) = event.as_ref().and_then(parse_nip35_details) else {
+        return Ok(HttpResponse::NotFound().body("Torrent not found"));
+    };
+
+    let torrent = details.torrent;
+    match client
+        .add_magnet(&torrent.magnet, qs.get("category"), qs.get("save_path"))
+        .await
+    {
+        Ok(()) => {
+            info!("Torrent {} sent to {}", torrent.name, client_name);
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "client": client_name,
+                "id": torrent.id,
+                "name": torrent.name,
+            })))
+        }
+        Err(e) => {
+            warn!("Could not send {} to {}: {}", torrent.name, client_name, e);
+            Ok(HttpResponse::BadGateway().body(e))
+        }
+    }
+}
+
+#[get("/torrent/{id}/details")]
+pub async fn torrent_details(
+    nostr: web::Data<NostrClient>,
+    req_data: HttpRequest,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let id = req_data.match_info().get("id").unwrap_or("");
+
+    let event = nostr
+        .get_event(id)
+        .await
+        .map_err(|e| format!("Relay error: {}", e))?;
+
+    match event.as_ref().and_then(parse_nip35_details) {
+        Some(details) => Ok(HttpResponse::Ok().json(details)),
         None => Ok(HttpResponse::NotFound().body("Torrent not found")),
     }
 }
@@ -0,0 +1,250 @@
+use crate::VERSION;
+use crate::categories::{CATEGORIES_CACHE, newznab_to_cat_ids};
+use crate::config::Config;
+use crate::dbs::DbQueryType::*;
+use crate::episodes::parse_number_param;
+use crate::feed::Enclosure;
+use crate::nostr::NostrClient;
+use crate::parser::Torrent;
+use crate::rest::search::{
+    batch_best_search, episode_search, requested_external_ids, tagged_search, titles_episode_search,
+};
+use crate::search::{Order, Sort, search};
+use crate::torznab::*;
+use actix_web::{HttpRequest, HttpResponse, get, web};
+use qstring::QString;
+
+#[get("/api")]
+pub async fn torznab_api(
+    nostr: web::Data<NostrClient>,
+    config: web::Data<Config>,
+    req_data: HttpRequest,
+) -> HttpResponse {
+    let qs = QString::from(req_data.query_string());
+    debug!("Received Torznab query: {}", req_data.query_string());
+
+    match qs.get("t").unwrap_or("") {
+        "caps" => {
+            let Some(categories) = CATEGORIES_CACHE.get() else {
+                return xml_error(ERR_UNKNOWN, "Categories not initialized");
+            };
+            HttpResponse::Ok()
+                .content_type("application/xml; charset=utf-8")
+                .body(caps_xml(VERSION, categories))
+        }
+        "search" | "tvsearch" | "movie" => {
+            let base_url = base_url(&req_data);
+            // `.torrent` files are only served from the configured caches
+            let enclosure = match config.torrent_cache_urls.is_empty() {
+                true => Enclosure::Magnet,
+                false => Enclosure::Torrent,
+            };
+            match torznab_search(&nostr, &config, &qs).await {
+                Ok(torrents) => {
+                    info!("{} torrents found via Torznab", torrents.len());
+                    HttpResponse::Ok()
+                        .content_type("application/rss+xml; charset=utf-8")
+                        .body(results_xml(
+                            &torrents,
+                            &base_url,
+                            qs.get("apikey"),
+                            enclosure,
+                        ))
+                }
+                Err((code, description)) => xml_error(code, &description),
+            }
+        }
+        "" => xml_error(ERR_MISSING_PARAM, "Missing parameter (t)"),
+        other => xml_error(ERR_NO_FUNCTION, &format!("No such function ({})", other)),
+    }
+}
+
+async fn torznab_search(
+    nostr: &NostrClient,
+    config: &Config,
+    qs: &QString,
+) -> Result<Vec<Torrent>, (u16, String)> {
+    let query = qs.get("q").unwrap_or("").trim();
+
+    let offset = parse_number(qs, "offset")?.unwrap_or(0);
+    let limit = parse_number(qs, "limit")?
+        .unwrap_or(MAX_RESULTS)
+        .min(MAX_RESULTS);
+    let wanted = offset + limit;
+
+    let mut cats: Vec<usize> = Vec::new();
+    if let Some(raw) = qs.get("cat") {
+        for part in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let id = part
+                .parse::<usize>()
+                .map_err(|_| (ERR_INCORRECT_PARAM, format!("Invalid category ({})", part)))?;
+            for cat_id in newznab_to_cat_ids(id) {
+                if !cats.contains(&cat_id) {
+                    cats.push(cat_id);
+                }
+            }
+        }
+    }
+
+    // A single site category can be filtered on the relay; larger sets are filtered
+    // locally to avoid fanning out one REQ per category.
+    let category = match cats.as_slice() {
+        [single] => Some(*single),
+        _ => None,
+    };
+
+    let (sort, order) = match query.is_empty() {
+        true => (Some(Sort::PublishDate), Some(Order::Descending)),
+        false => (None, None),
+    };
+
+    let imdbid = qs
+        .get("imdbid")
+        .filter(|s| !s.is_empty())
+        .map(|id| match id.starts_with("tt") {
+            true => id.to_string(),
+            false => format!("tt{}", id),
+        });
+    let tmdbid = qs.get("tmdbid").filter(|s| !s.is_empty());
+    let tvdbid = qs.get("tvdbid").filter(|s| !s.is_empty());
+    let season = qs.get("season").and_then(parse_number_param);
+    let episode = qs.get("ep").and_then(parse_number_param);
+
+    // tvsearch IDs refer to TV shows, a TMDB ID is a movie otherwise
+    let is_tv = qs.get("t") == Some("tvsearch") || season.is_some();
+    let tmdb_type = if is_tv { TMDBTV } else { TMDB };
+    let db_ids = [
+        (tmdbid.map(str::to_string), tmdb_type),
+        (tvdbid.map(str::to_string), TVDB),
+        (imdbid.clone(), IMDB),
+    ];
+    let has_db_id = db_ids.iter().any(|(id, _)| id.is_some());
+    let db_id = db_ids
+        .into_iter()
+        .filter(|(_, db_type)| crate::dbs::can_resolve(*db_type))
+        .find_map(|(id, db_type)| id.map(|id| (id, db_type)));
+
+    // Events tagged with the requested IDs are exact matches, titles are only a fallback
+    let external_ids = requested_external_ids(imdbid.as_deref(), tmdbid, is_tv);
+    let tagged = tagged_search(
+        nostr,
+        &external_ids,
+        category.map(|c| vec![c]),
+        season,
+        episode,
+        sort,
+        order,
+        None,
+        wanted,
+    )
+    .await
+    .0;
+
+    let mut torrents = match db_id {
+        _ if !tagged.is_empty() => tagged,
+        Some((id, db_type)) => {
+            let episode_filter = season.map(|season| (season, episode));
+            id_search(
+                nostr,
+                id,
+                config,
+                db_type,
+                category,
+                episode_filter,
+                sort,
+                order,
+                wanted,
+            )
+            .await
+        }
+        None if has_db_id && query.is_empty() => {
+            warn!(
+                "Database ID provided but no resolver can handle it and no query - returning empty result"
+            );
+            Vec::new()
+        }
+        _ => match season {
+            Some(season) => {
+                let cats = category.map(|c| vec![c]);
+                episode_search(
+                    nostr, query, season, episode, cats, sort, order, None, wanted,
+                )
+                .await
+                .map(|(torrents, _)| torrents)
+            }
+            None => search(nostr, query, category, sort, order, None, wanted)
+                .await
+                .map(|page| page.torrents),
+        }
+        .map_err(|e| (ERR_UNKNOWN, format!("Relay error: {}", e)))?,
+    };
+
+    if !cats.is_empty() {
+        torrents.retain(|t| cats.contains(&t.category_id));
+    }
+
+    Ok(torrents.into_iter().skip(offset).take(limit).collect())
+}
+
+/// Search the titles of a TMDB/IMDB/TVDB entry, restricted to a season (and episode) if given.
+#[allow(clippy::too_many_arguments)]
+async fn id_search(
+    nostr: &NostrClient,
+    id: String,
+    config: &Config,
+    db_type: crate::dbs::DbQueryType,
+    category: Option<usize>,
+    episode_filter: Option<(u32, Option<u32>)>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    limit: usize,
+) -> Vec<Torrent> {
+    let queries = match crate::dbs::get_queries(id.clone(), config, db_type).await {
+        Ok(queries) => queries,
+        Err(e) => {
+            warn!("Failed to get database queries for ID {}: {}", id, e);
+            return Vec::new();
+        }
+    };
+
+    let result = match episode_filter {
+        Some((season, episode)) => {
+            let cats = category.map(|c| vec![c]);
+            titles_episode_search(
+                nostr, queries, season, episode, cats, sort, order, None, limit,
+            )
+            .await
+        }
+        None => batch_best_search(nostr, queries, category, sort, order, None, limit).await,
+    };
+    result.map(|(torrents, _)| torrents).unwrap_or_else(|e| {
+        warn!("Torznab ID search failed for {}: {}", id, e);
+        Vec::new()
+    })
+}
+
+fn parse_number(qs: &QString, key: &str) -> Result<Option<usize>, (u16, String)> {
+    match qs.get(key).filter(|s| !s.is_empty()) {
+        Some(raw) => raw
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| (ERR_INCORRECT_PARAM, format!("Invalid {} ({})", key, raw))),
+        None => Ok(None),
+    }
+}
+
+/// Externally visible address of this server, as seen by the client.
+pub(crate) fn base_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+fn xml_error(code: u16, description: &str) -> HttpResponse {
+    let mut response = match code {
+        ERR_UNKNOWN => HttpResponse::InternalServerError(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response
+        .content_type("application/xml; charset=utf-8")
+        .body(error_xml(code, description))
+}
@@ -0,0 +1,39 @@
+use crate::watch::{WatchRule, get_watch_store};
+use actix_web::{HttpRequest, HttpResponse, delete, get, post, web};
+
+#[get("/watch")]
+pub async fn list_watch_rules() -> HttpResponse {
+    match get_watch_store() {
+        Some(store) => HttpResponse::Ok().json(store.list()),
+        None => HttpResponse::ServiceUnavailable().body("Watch rules not initialized"),
+    }
+}
+
+#[post("/watch")]
+pub async fn add_watch_rule(rule: web::Json<WatchRule>) -> HttpResponse {
+    let Some(store) = get_watch_store() else {
+        return HttpResponse::ServiceUnavailable().body("Watch rules not initialized");
+    };
+    match store.add(rule.into_inner()).await {
+        Ok(rule) => {
+            info!("Watch rule {} added: {}", rule.id, rule.name);
+            HttpResponse::Created().json(rule)
+        }
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+#[delete("/watch/{id}")]
+pub async fn delete_watch_rule(req_data: HttpRequest) -> HttpResponse {
+    let Some(store) = get_watch_store() else {
+        return HttpResponse::ServiceUnavailable().body("Watch rules not initialized");
+    };
+    let id = req_data.match_info().get("id").unwrap_or("");
+    match store.remove(id).await {
+        true => {
+            info!("Watch rule {} removed", id);
+            HttpResponse::NoContent().finish()
+        }
+        false => HttpResponse::NotFound().body("Unknown watch rule"),
+    }
+}
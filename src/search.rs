@@ -1,57 +1,491 @@
+use crate::ban_words::{BanWords, global_ban_words};
+use crate::cache::{CacheKey, get_search_cache};
 use crate::categories::cat_id_to_nostr_tag;
-use crate::nostr::NostrClient;
-use crate::parser::Torrent;
+use crate::filters::SearchFilter;
+use crate::index::{LocalIndex, get_local_index};
+use crate::nostr::{NostrClient, SearchBatch};
+use crate::parser::{ExternalId, Torrent};
 use crate::rate_limiter::RateLimiter;
+use std::collections::HashSet;
+use std::future::Future;
 use std::str::FromStr;
 use std::sync::OnceLock;
 
 static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
 
 pub(crate) fn get_rate_limiter() -> &'static RateLimiter {
-    RATE_LIMITER.get_or_init(|| RateLimiter::default())
+    RATE_LIMITER.get_or_init(RateLimiter::default)
 }
 
+/// Default and maximum number of results per page.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 500;
+/// Maximum number of events requested in a single REQ while paging.
+const RELAY_BATCH_SIZE: usize = 100;
+/// Events read per step when walking the local index.
+const INDEX_BATCH_SIZE: usize = 1000;
+/// REQs allowed on top of the ones needed to reach the end of the page, for the events
+/// dropped by filters. A walk running out of them stops with a cursor to resume from.
+const MAX_PAGE_REQUESTS: usize = 10;
+/// Length of the event ID prefixes kept in a cursor.
+const CURSOR_ID_LEN: usize = 16;
+
+/// A window into the results of a search, newest first.
+/// `since`/`until` are unix timestamps bounding the event `created_at`, both inclusive.
+/// With a `cursor`, `offset` counts from the cursor instead of the newest result.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub cursor: Option<Cursor>,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page {
+            offset: 0,
+            limit: DEFAULT_PAGE_SIZE,
+            since: None,
+            until: None,
+            cursor: None,
+        }
+    }
+}
+
+impl Page {
+    /// Number of results to collect before the page can be cut.
+    pub fn end(&self) -> usize {
+        self.offset + self.limit
+    }
+
+    /// Cut an already collected result list to this page. Returns the page and the
+    /// offset of the next one, `None` when there is nothing left.
+    pub fn slice(&self, mut torrents: Vec<Torrent>) -> (Vec<Torrent>, Option<usize>) {
+        let next_offset = (torrents.len() > self.end()).then(|| self.end());
+        torrents.truncate(self.end());
+        let page = torrents.split_off(self.offset.min(torrents.len()));
+        (page, next_offset)
+    }
+}
+
+/// Where a relay walk stopped: the `created_at` of the last event handled and the IDs
+/// handled at that second. Unlike an offset, it stays valid while new torrents get published.
+/// Written as `{until}.{id}.{id}…` with shortened IDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub until: u64,
+    pub seen: Vec<String>,
+}
+
+impl Cursor {
+    fn at(created_at: u64, id: &str) -> Self {
+        Cursor {
+            until: created_at,
+            seen: vec![short_id(id)],
+        }
+    }
+
+    /// Whether the event was handled before the cursor.
+    fn covers(&self, created_at: u64, id: &str) -> bool {
+        created_at > self.until || (created_at == self.until && self.seen.contains(&short_id(id)))
+    }
+
+    /// Move the cursor past an event, events come newest first.
+    fn advance(cursor: &mut Option<Cursor>, created_at: u64, id: &str) {
+        match cursor {
+            Some(cursor) if cursor.until == created_at => cursor.seen.push(short_id(id)),
+            _ => *cursor = Some(Cursor::at(created_at, id)),
+        }
+    }
+}
+
+fn short_id(id: &str) -> String {
+    id.chars().take(CURSOR_ID_LEN).collect()
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.until)?;
+        self.seen.iter().try_for_each(|id| write!(f, ".{}", id))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Curseur invalide : {}", s);
+        let mut parts = s.split('.');
+        let until = parts
+            .next()
+            .and_then(|until| until.parse().ok())
+            .ok_or_else(invalid)?;
+        let seen: Vec<String> = parts.map(str::to_string).collect();
+        if seen
+            .iter()
+            .any(|id| id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(invalid());
+        }
+        Ok(Cursor { until, seen })
+    }
+}
+
+pub struct SearchPage {
+    pub torrents: Vec<Torrent>,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<Cursor>,
+}
+
+/// The first `limit` results of a search. `next_offset` is set when there are more.
 pub async fn search(
     nostr: &NostrClient,
     name: &str,
     category: Option<usize>,
     sort: Option<Sort>,
     order: Option<Order>,
-    ban_words: Option<Vec<String>>,
+    ban_words: Option<BanWords>,
+    limit: usize,
+) -> Result<SearchPage, Box<dyn std::error::Error + Send + Sync>> {
+    let page = Page {
+        limit,
+        ..Page::default()
+    };
+    let filter = SearchFilter::default();
+    search_page(
+        nostr, name, category, sort, order, ban_words, &filter, &page,
+    )
+    .await
+}
+
+/// Search a single page of results. Ban words and filters are applied while walking the
+/// relay, so `offset` and `limit` count filtered results. Sorting applies to the page only,
+/// so pages are only stable with the default date order.
+#[allow(clippy::too_many_arguments)]
+pub async fn search_page(
+    nostr: &NostrClient,
+    name: &str,
+    category: Option<usize>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    filter: &SearchFilter,
+    page: &Page,
+) -> Result<SearchPage, Box<dyn std::error::Error + Send + Sync>> {
+    let bans = ban_lists(ban_words.as_ref());
+    let keep = |t: &Torrent| !is_banned(&bans, t) && filter.matches(t);
+    // Events are never created before their publication date, so `after` can bound the
+    // relay walk. `before` cannot: backfilled events are created long after publication.
+    let since = page.since.max(filter.after);
+    let mut walk = fetch(nostr, name, category, page, since, keep).await?;
+
+    Torrent::sort(&mut walk.torrents, sort, order);
+    let next_offset = match &walk.next_cursor {
+        Some(_) if !walk.torrents.is_empty() => Some(page.offset + walk.torrents.len()),
+        _ => None,
+    };
+    Ok(SearchPage {
+        torrents: walk.torrents,
+        next_offset,
+        next_cursor: walk.next_cursor,
+    })
+}
+
+/// Torrents whose NIP-35 `i` tags reference any of `external_ids`, an exact match unlike
+/// title searches.
+pub async fn external_id_search(
+    nostr: &NostrClient,
+    external_ids: &[ExternalId],
+    category: Option<usize>,
+    sort: Option<Sort>,
+    order: Option<Order>,
+    ban_words: Option<BanWords>,
+    limit: usize,
 ) -> Result<Vec<Torrent>, Box<dyn std::error::Error + Send + Sync>> {
+    debug!("Searching via Nostr (external IDs: {:?})", external_ids);
+    let tag_filter = category.and_then(cat_id_to_nostr_tag);
+
+    let mut torrents = match get_local_index().filter(|index| index.is_ready()) {
+        Some(index) => index.search_external_ids(external_ids, tag_filter, limit),
+        None => {
+            let values: Vec<String> = external_ids.iter().map(|id| id.to_string()).collect();
+            let key = CacheKey::external_ids(&values, tag_filter, limit);
+            get_search_cache()
+                .get_or_fetch(key, || async {
+                    let _guard = get_rate_limiter().acquire().await;
+                    nostr.search_external_ids(&values, tag_filter, limit).await
+                })
+                .await?
+                .torrents
+        }
+    };
+    let bans = ban_lists(ban_words.as_ref());
+    torrents.retain(|t| !is_banned(&bans, t));
+    Torrent::sort(&mut torrents, sort, order);
+    Ok(torrents)
+}
+
+/// What a walk collected.
+struct Walk {
+    torrents: Vec<Torrent>,
+    /// Where to resume, `None` once the source ran out
+    next_cursor: Option<Cursor>,
+}
+
+/// Collect the page of torrents accepted by `keep`, newest first, from the local index
+/// when it is ready and from the relays otherwise.
+async fn fetch(
+    nostr: &NostrClient,
+    name: &str,
+    category: Option<usize>,
+    page: &Page,
+    since: Option<u64>,
+    keep: impl Fn(&Torrent) -> bool,
+) -> Result<Walk, Box<dyn std::error::Error + Send + Sync>> {
     debug!(
-        "Searching via Nostr (query: {:?}, category: {:?})",
-        name, category
+        "Searching via Nostr (query: {:?}, category: {:?}, page: {:?})",
+        name, category, page
     );
 
-    let _guard = get_rate_limiter().acquire().await;
-
-    let tag_filter = category.and_then(|id| cat_id_to_nostr_tag(id));
+    let tag_filter = category.and_then(cat_id_to_nostr_tag);
+    let local_index = get_local_index();
+    let from_index = async |index: &LocalIndex| {
+        walk(page, INDEX_BATCH_SIZE, &keep, |until, limit| async move {
+            Ok(index.search(name, tag_filter, limit, since, until))
+        })
+        .await
+    };
 
     let start = std::time::Instant::now();
-    let mut torrents = nostr.search(name, tag_filter, 100).await?;
-    debug!("Got {} results in {:?}", torrents.len(), start.elapsed());
-
-    if let Some(ban_words) = ban_words {
-        torrents.retain(|t| {
-            !ban_words
-                .iter()
-                .any(|w| t.name.to_lowercase().contains(&w.to_lowercase()))
+    if let Some(index) = local_index.filter(|index| index.is_ready()) {
+        let result = from_index(index).await?;
+        debug!(
+            "Got {} results in {:?}",
+            result.torrents.len(),
+            start.elapsed()
+        );
+        return Ok(result);
+    }
+
+    let result = walk(page, RELAY_BATCH_SIZE, &keep, |until, limit| async move {
+        let key = CacheKey::new(name, tag_filter, limit, since, until);
+        get_search_cache()
+            .get_or_fetch(key, || async {
+                let _guard = get_rate_limiter().acquire().await;
+                nostr.search(name, tag_filter, limit, since, until).await
+            })
+            .await
+    })
+    .await;
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => match local_index.filter(|index| index.len() > 0) {
+            Some(index) => {
+                warn!("Relay search failed ({}), answering from local index", e);
+                from_index(index).await?
+            }
+            None => return Err(e),
+        },
+    };
+
+    debug!(
+        "Got {} results in {:?}",
+        result.torrents.len(),
+        start.elapsed()
+    );
+    Ok(result)
+}
+
+/// Walk a source newest first, moving `until` backwards, and cut the page out of the
+/// torrents accepted by `keep`. Only an empty batch means the source ran out: relays may
+/// answer fewer events than asked. A failure after the first batch returns the partial page.
+async fn walk<F, Fut>(
+    page: &Page,
+    batch_size: usize,
+    keep: impl Fn(&Torrent) -> bool,
+    mut next_batch: F,
+) -> Result<Walk, Box<dyn std::error::Error + Send + Sync>>
+where
+    F: FnMut(Option<u64>, usize) -> Fut,
+    Fut: Future<Output = Result<SearchBatch, Box<dyn std::error::Error + Send + Sync>>>,
+{
+    let mut torrents: Vec<Torrent> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut skipped = 0;
+    let mut position = page.cursor.clone();
+    let mut until = page.cursor.as_ref().map(|c| c.until).or(page.until);
+    let requests = MAX_PAGE_REQUESTS + (page.end() + 1).div_ceil(batch_size);
+
+    for request in 0..requests {
+        let batch = match next_batch(until, batch_size).await {
+            Ok(batch) => batch,
+            Err(e) if request == 0 => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Relay search failed while paging, returning partial results: {}",
+                    e
+                );
+                break;
+            }
+        };
+        let Some(oldest) = batch.oldest_created_at.filter(|_| batch.event_count > 0) else {
+            return Ok(Walk {
+                torrents,
+                next_cursor: None,
+            });
+        };
+
+        let mut events: Vec<(u64, Torrent)> = batch
+            .torrents
+            .into_iter()
+            .map(|t| (batch.created_at.get(&t.id).copied().unwrap_or(oldest), t))
+            .collect();
+        events.sort_by_key(|(created_at, _)| std::cmp::Reverse(*created_at));
+
+        let mut added = 0;
+        for (created_at, torrent) in events {
+            let before_cursor = page
+                .cursor
+                .as_ref()
+                .is_some_and(|c| c.covers(created_at, &torrent.id));
+            if before_cursor || !seen.insert(torrent.id.clone()) {
+                continue;
+            }
+            added += 1;
+            let kept = keep(&torrent);
+            if kept && skipped == page.offset && torrents.len() == page.limit {
+                // One more result than the page holds: the next page is not empty
+                return Ok(Walk {
+                    torrents,
+                    next_cursor: position,
+                });
+            }
+            Cursor::advance(&mut position, created_at, &torrent.id);
+            match kept {
+                true if skipped < page.offset => skipped += 1,
+                true => torrents.push(torrent),
+                false => {}
+            }
+        }
+
+        if added > 0 {
+            until = Some(oldest);
+            continue;
+        }
+        // `until` is inclusive: step past the oldest second once a batch brings nothing new
+        let Some(before) = oldest.checked_sub(1) else {
+            return Ok(Walk {
+                torrents,
+                next_cursor: None,
+            });
+        };
+        until = Some(before);
+        position = Some(Cursor {
+            until: before,
+            seen: Vec::new(),
         });
     }
 
-    Torrent::sort(&mut torrents, sort, order);
-    Ok(torrents)
+    // Out of requests: more results may exist past the cursor
+    Ok(Walk {
+        torrents,
+        next_cursor: position,
+    })
+}
+
+/// The global ban list and the request `ban_words`, when not empty.
+fn ban_lists(ban_words: Option<&BanWords>) -> Vec<&BanWords> {
+    global_ban_words()
+        .into_iter()
+        .chain(ban_words)
+        .filter(|b| !b.is_empty())
+        .collect()
+}
+
+fn is_banned(lists: &[&BanWords], torrent: &Torrent) -> bool {
+    lists.iter().any(|b| b.is_banned(&torrent.name))
+}
+
+/// Maximum number of relaxed variants sent to the relay for an extended search.
+const MAX_RELAXED_QUERIES: usize = 5;
+
+/// Lowercase alphanumeric tokens of a query or release name.
+pub fn tokenize(s: &str) -> Vec<String> {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Wrap a query in double quotes for NIP-50 exact-phrase matching.
+pub fn quote_phrase(query: &str) -> String {
+    let query = query.trim().trim_matches('"').trim();
+    if query.is_empty() {
+        return String::new();
+    }
+    format!("\"{}\"", query)
+}
+
+/// Build relaxed variants of a query for the extended search mode: the full token list,
+/// the significant tokens only, the query without its first word, then the query with
+/// trailing tokens dropped one by one.
+pub fn relaxed_queries(query: &str) -> Vec<String> {
+    let tokens = tokenize(query);
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let significant: Vec<String> = tokens
+        .iter()
+        .filter(|t| t.chars().count() > 2 || t.chars().all(|c| c.is_ascii_digit()))
+        .cloned()
+        .collect();
+
+    let mut candidates = vec![tokens.join(" "), significant.join(" ")];
+    if tokens.len() > 2 {
+        candidates.push(tokens[1..].join(" "));
+    }
+    // Never go down to a single word when the query has more than two
+    let min_prefix = if tokens.len() > 2 { 2 } else { 1 };
+    for n in (min_prefix..tokens.len()).rev() {
+        candidates.push(tokens[..n].join(" "));
+    }
+
+    let mut queries: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if !candidate.is_empty() && !queries.contains(&candidate) {
+            queries.push(candidate);
+        }
+    }
+    queries.truncate(MAX_RELAXED_QUERIES);
+    queries
+}
+
+/// Number of query tokens present in a release name.
+pub fn token_coverage(query_tokens: &[String], name: &str) -> usize {
+    let name_tokens = tokenize(name);
+    query_tokens
+        .iter()
+        .filter(|t| name_tokens.contains(t))
+        .count()
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum Sort {
     Name,
     Seed,
+    /// Kept for the Jackett definitions, Nostr events carry no comments: sorts by date
     Comments,
     PublishDate,
     Completed,
     Leech,
+    /// Score of a [`crate::ranking::RankingProfile`]
+    Relevance,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -71,6 +505,7 @@ impl FromStr for Sort {
             "publish_date" => Ok(Sort::PublishDate),
             "completed" => Ok(Sort::Completed),
             "leech" => Ok(Sort::Leech),
+            "relevance" => Ok(Sort::Relevance),
             _ => Err(format!("Valeur de tri invalide : {}", s)),
         }
     }
@@ -87,3 +522,185 @@ impl FromStr for Order {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn torrents(count: usize) -> Vec<Torrent> {
+        (0..count)
+            .map(|i| Torrent {
+                id: i.to_string(),
+                name: format!("Torrent {}", i),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_page_slice() {
+        let page = Page {
+            offset: 2,
+            limit: 3,
+            ..Page::default()
+        };
+        let (slice, next) = page.slice(torrents(10));
+        let ids: Vec<&str> = slice.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["2", "3", "4"]);
+        assert_eq!(next, Some(5));
+
+        let (slice, next) = page.slice(torrents(4));
+        assert_eq!(slice.len(), 2);
+        assert_eq!(next, None);
+
+        let (slice, next) = page.slice(torrents(1));
+        assert!(slice.is_empty());
+        assert_eq!(next, None);
+    }
+
+    /// A relay holding `count` events, one per second, answering at most 50 events per REQ.
+    fn relay(count: u64, until: Option<u64>, limit: usize) -> SearchBatch {
+        let Some(until) = until.unwrap_or(u64::MAX).min(count).checked_sub(1) else {
+            return SearchBatch {
+                torrents: Vec::new(),
+                event_count: 0,
+                oldest_created_at: None,
+                created_at: HashMap::new(),
+            };
+        };
+        let torrents: Vec<Torrent> = (0..=until)
+            .rev()
+            .take(limit.min(50))
+            .map(|i| Torrent {
+                id: format!("{:04x}", i),
+                name: format!("Torrent {}", i),
+                ..Default::default()
+            })
+            .collect();
+        let created_at: HashMap<String, u64> = torrents
+            .iter()
+            .map(|t| (t.id.clone(), u64::from_str_radix(&t.id, 16).unwrap()))
+            .collect();
+        SearchBatch {
+            event_count: torrents.len(),
+            oldest_created_at: created_at.values().min().copied(),
+            torrents,
+            created_at,
+        }
+    }
+
+    async fn walk_relay(count: u64, page: &Page, keep: impl Fn(&Torrent) -> bool) -> Walk {
+        walk(page, RELAY_BATCH_SIZE, keep, |until, limit| async move {
+            Ok(relay(count, until, limit))
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_walk_collects_filtered_results() {
+        // Only one event in ten passes the filter, the page needs several batches
+        let keep = |t: &Torrent| t.name.ends_with('0');
+        let page = Page {
+            offset: 5,
+            limit: 20,
+            ..Page::default()
+        };
+        let result = walk_relay(1000, &page, keep).await;
+        let names: Vec<&str> = result.torrents.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names.len(), 20);
+        assert_eq!(names[0], "Torrent 940");
+        assert_eq!(names[19], "Torrent 750");
+
+        // The cursor resumes right after the page, whatever the offset was
+        let cursor = result.next_cursor.unwrap();
+        // The events up to the next kept one were handled already
+        assert_eq!(cursor, Cursor::at(741, "02e5"));
+        let next = Page {
+            cursor: Some(cursor.to_string().parse().unwrap()),
+            ..Page::default()
+        };
+        let result = walk_relay(1000, &next, keep).await;
+        assert_eq!(result.torrents[0].name, "Torrent 740");
+
+        // Out of requests before the page is full: the cursor picks up where the walk stopped
+        assert_eq!(result.torrents.len(), 60);
+        let next = Page {
+            cursor: result.next_cursor,
+            ..Page::default()
+        };
+        let result = walk_relay(1000, &next, keep).await;
+        assert_eq!(result.torrents.len(), 15);
+        assert_eq!(result.torrents[14].name, "Torrent 0");
+        assert_eq!(result.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn test_walk_runs_out_on_empty_batch() {
+        // Short batches do not end the walk, only the empty one does
+        let result = walk_relay(130, &Page::default(), |_| true).await;
+        assert_eq!(result.torrents.len(), 100);
+        assert_eq!(result.torrents[99].name, "Torrent 30");
+        assert_eq!(result.next_cursor, Some(Cursor::at(30, "001e")));
+
+        let result = walk_relay(100, &Page::default(), |_| true).await;
+        assert_eq!(result.torrents.len(), 100);
+        assert_eq!(result.next_cursor, None);
+
+        // Deep offsets are reachable
+        let page = Page {
+            offset: 1000,
+            limit: 10,
+            ..Page::default()
+        };
+        let result = walk_relay(5000, &page, |_| true).await;
+        assert_eq!(result.torrents[0].name, "Torrent 3999");
+    }
+
+    #[test]
+    fn test_query_helpers() {
+        assert_eq!(
+            tokenize("Dune: Part Two (2024) 1080p"),
+            ["dune", "part", "two", "2024", "1080p"]
+        );
+        assert!(tokenize(" - ").is_empty());
+
+        assert_eq!(quote_phrase(" dune part two "), "\"dune part two\"");
+        assert_eq!(quote_phrase("\"dune\""), "\"dune\"");
+        assert_eq!(quote_phrase("  "), "");
+
+        let tokens = tokenize("Vaiana 2");
+        assert_eq!(token_coverage(&tokens, "Vaiana.2.2024.MULTi.1080p"), 2);
+        assert_eq!(token_coverage(&tokens, "Vaiana 2016 1080p"), 1);
+        assert_eq!(token_coverage(&tokens, "Moana"), 0);
+    }
+
+    #[test]
+    fn test_relaxed_queries() {
+        assert_eq!(
+            relaxed_queries("Le Seigneur des Anneaux 2001"),
+            [
+                "le seigneur des anneaux 2001",
+                "seigneur des anneaux 2001",
+                "le seigneur des anneaux",
+                "le seigneur des",
+                "le seigneur",
+            ]
+        );
+        // Never below two words when the query has more than two
+        assert_eq!(relaxed_queries("Vaiana 2"), ["vaiana 2", "vaiana"]);
+        assert!(relaxed_queries("").is_empty());
+    }
+
+    #[test]
+    fn test_cursor_parse() {
+        let cursor: Cursor = "1700000000.ab12.cd34".parse().unwrap();
+        assert_eq!(cursor.until, 1_700_000_000);
+        assert_eq!(cursor.seen, ["ab12", "cd34"]);
+        assert_eq!(cursor.to_string(), "1700000000.ab12.cd34");
+        assert!("1700000000".parse::<Cursor>().unwrap().seen.is_empty());
+        assert!("abc".parse::<Cursor>().is_err());
+        assert!("12..ab".parse::<Cursor>().is_err());
+    }
+}
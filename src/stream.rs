@@ -0,0 +1,192 @@
+use crate::ban_words::{BanWords, global_ban_words};
+use crate::categories::CATEGORIES_CACHE;
+use crate::filters::SearchFilter;
+use crate::index::LocalIndex;
+use crate::nostr::{ALLOWED_PUBKEY, NostrClient, parse_nip35_event};
+use crate::parser::Torrent;
+use crate::search::{token_coverage, tokenize};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc};
+
+/// Torrents buffered per client before a slow one starts missing events.
+const CLIENT_BUFFER: usize = 256;
+/// Event ids remembered to drop the ones replayed after a resubscription.
+const RECENT_IDS: usize = 512;
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
+static LIVE_TORRENTS: OnceLock<broadcast::Sender<Torrent>> = OnceLock::new();
+
+/// Receive every new torrent published from now on, `None` before [`init_live_stream`].
+pub fn subscribe_live() -> Option<broadcast::Receiver<Torrent>> {
+    LIVE_TORRENTS.get().map(|tx| tx.subscribe())
+}
+
+/// Keep the kind-2003 subscription open in the background and broadcast the new torrents.
+/// It also feeds the local index, starting from its newest event to catch up on the downtime.
+pub fn init_live_stream(nostr: Arc<NostrClient>, index: Option<Arc<LocalIndex>>) {
+    let (tx, _) = broadcast::channel(CLIENT_BUFFER);
+    if LIVE_TORRENTS.set(tx.clone()).is_err() {
+        warn!("Live stream already initialized");
+        return;
+    }
+    tokio::spawn(async move { live_subscription(nostr, tx, index).await });
+}
+
+async fn live_subscription(
+    nostr: Arc<NostrClient>,
+    live: broadcast::Sender<Torrent>,
+    index: Option<Arc<LocalIndex>>,
+) {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut since = index
+        .as_ref()
+        .and_then(|index| index.newest())
+        .map_or(started, |newest| newest.min(started));
+    let mut recent: VecDeque<String> = VecDeque::with_capacity(RECENT_IDS);
+
+    loop {
+        let filter = json!({
+            "kinds": [2003],
+            "authors": [ALLOWED_PUBKEY],
+            "since": since
+        });
+        let (tx, mut rx) = mpsc::channel::<Value>(CLIENT_BUFFER);
+        let subscription = nostr.subscribe(filter, tx);
+        let forward = async {
+            while let Some(event) = rx.recv().await {
+                let created_at = event["created_at"].as_u64().unwrap_or(0);
+                since = since.max(created_at);
+                let indexed = match &index {
+                    Some(index) => index.add(std::slice::from_ref(&event)).await,
+                    None => 0,
+                };
+                if indexed > 0 {
+                    debug!("Local index: new event {:?}", event["id"]);
+                }
+                // Catching up for the index, clients only get what was published since startup
+                if created_at < started {
+                    continue;
+                }
+                let Some(torrent) = parse_nip35_event(event) else {
+                    continue;
+                };
+                // `since` is inclusive, the last events come again after a resubscription
+                if recent.contains(&torrent.id) {
+                    continue;
+                }
+                if recent.len() == RECENT_IDS {
+                    recent.pop_front();
+                }
+                recent.push_back(torrent.id.clone());
+                debug!("Live stream: new torrent {}", torrent.name);
+                // Fails only when no client is connected
+                let _ = live.send(torrent);
+            }
+        };
+        let (result, _) = tokio::join!(subscription, forward);
+        if let Err(e) = result {
+            debug!("Live stream subscription ended: {}", e);
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+/// What a `/stream` client asked for, with the same parameters as `/search`.
+#[derive(Debug, Clone, Default)]
+pub struct StreamFilter {
+    /// Site categories, a parent category includes its sub-categories
+    pub categories: Vec<usize>,
+    /// Words that must all appear in the name
+    pub query_tokens: Vec<String>,
+    pub ban_words: Option<BanWords>,
+    pub filter: SearchFilter,
+}
+
+impl StreamFilter {
+    pub fn new(
+        categories: Vec<usize>,
+        query: &str,
+        ban_words: Option<BanWords>,
+        filter: SearchFilter,
+    ) -> Self {
+        StreamFilter {
+            categories,
+            query_tokens: tokenize(query),
+            ban_words,
+            filter,
+        }
+    }
+
+    pub fn matches(&self, torrent: &Torrent) -> bool {
+        let in_category = self.categories.is_empty()
+            || self
+                .categories
+                .iter()
+                .any(|c| category_contains(*c, torrent.category_id));
+        in_category
+            && token_coverage(&self.query_tokens, &torrent.name) == self.query_tokens.len()
+            && !global_ban_words()
+                .into_iter()
+                .chain(self.ban_words.as_ref())
+                .any(|b| b.is_banned(&torrent.name))
+            && self.filter.matches(torrent)
+    }
+}
+
+fn category_contains(category: usize, id: usize) -> bool {
+    category == id
+        || CATEGORIES_CACHE.get().is_some_and(|cats| {
+            cats.iter()
+                .filter(|c| c.id == category)
+                .any(|c| c.sub_categories.iter().any(|sub| sub.id == id))
+        })
+}
+
+/// A torrent as a Server-Sent Event, `id` is the Nostr event id.
+pub fn sse_event(torrent: &Torrent) -> String {
+    format!(
+        "id: {}\nevent: torrent\ndata: {}\n\n",
+        torrent.id,
+        torrent.to_json()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qstring::QString;
+
+    fn torrent(name: &str, category_id: usize) -> Torrent {
+        Torrent {
+            id: "e1".to_string(),
+            category_id,
+            ..Torrent::named(name)
+        }
+    }
+
+    #[test]
+    fn test_stream_filter() {
+        let filter = StreamFilter::new(
+            vec![2183],
+            "dune part",
+            BanWords::parse(&["vfq".to_string()]).ok(),
+            SearchFilter::from_query(&QString::from("resolution=1080p")),
+        );
+        assert!(filter.matches(&torrent("Dune.Part.Two.2024.MULTi.1080p.WEB-DL", 2183)));
+        assert!(!filter.matches(&torrent("Dune.Part.Two.2024.MULTi.1080p.WEB-DL", 2184)));
+        assert!(!filter.matches(&torrent("Dune.2021.MULTi.1080p.WEB-DL", 2183)));
+        assert!(!filter.matches(&torrent("Dune.Part.Two.2024.VFQ.1080p.WEB-DL", 2183)));
+        assert!(!filter.matches(&torrent("Dune.Part.Two.2024.MULTi.720p.WEB-DL", 2183)));
+        assert!(StreamFilter::default().matches(&torrent("Anything", 2145)));
+
+        let event = sse_event(&torrent("Dune", 2183));
+        assert!(event.starts_with("id: e1\nevent: torrent\ndata: {"));
+        assert!(event.ends_with("}\n\n"));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::data_path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const CACHE_FILE: &str = "tmdb_cache.json";
+/// New entries are written out together at most this often.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+static TMDB_CACHE: OnceLock<TitleCache> = OnceLock::new();
+
+/// The TMDB title cache, `None` when disabled.
+pub fn get_tmdb_cache() -> Option<&'static TitleCache> {
+    TMDB_CACHE.get()
+}
+
+/// Load the cache file next to `config.json`. A `ttl` of 0 disables the cache.
+pub fn init_tmdb_cache(ttl: u64) {
+    if ttl == 0 {
+        return;
+    }
+    let cache = TitleCache::load(data_path(CACHE_FILE), ttl);
+    info!(
+        "TMDB cache loaded: {} entries from {}",
+        cache.len(),
+        cache.path.display()
+    );
+    if TMDB_CACHE.set(cache).is_err() {
+        warn!("TMDB cache already initialized");
+        return;
+    }
+    tokio::spawn(async {
+        let cache = get_tmdb_cache().unwrap();
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            cache.flush().await;
+        }
+    });
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedTitles {
+    titles: Vec<String>,
+    fetched_at: u64,
+}
+
+/// Resolved search titles per `(db, id, settings)`, persisted as a single JSON file.
+pub struct TitleCache {
+    path: PathBuf,
+    ttl: u64,
+    entries: Mutex<HashMap<String, CachedTitles>>,
+    /// Entries changed since the last write
+    dirty: AtomicBool,
+    /// Keeps concurrent writes of the file in order
+    writing: tokio::sync::Mutex<()>,
+}
+
+impl TitleCache {
+    fn load(path: PathBuf, ttl: u64) -> Self {
+        let mut entries: HashMap<String, CachedTitles> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupted TMDB cache {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let now = now();
+        entries.retain(|_, e| now.saturating_sub(e.fetched_at) < ttl);
+        TitleCache {
+            path,
+            ttl,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+            writing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn key(db: &str, id: &str, variant: &str) -> String {
+        format!("{}:{}:{}", db, id.trim().to_lowercase(), variant)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn get(&self, db: &str, id: &str, variant: &str) -> Option<Vec<String>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&Self::key(db, id, variant))
+            .filter(|e| now().saturating_sub(e.fetched_at) < self.ttl)
+            .map(|e| e.titles.clone())
+    }
+
+    /// Add titles, written out with the next [`TitleCache::flush`].
+    pub fn insert(&self, db: &str, id: &str, variant: &str, titles: Vec<String>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(
+            Self::key(db, id, variant),
+            CachedTitles {
+                titles,
+                fetched_at: now(),
+            },
+        );
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Drop every entry and write the empty cache. Returns the number of removed entries.
+    pub async fn purge(&self) -> usize {
+        let count = {
+            let mut entries = self.entries.lock().unwrap();
+            let count = entries.len();
+            entries.clear();
+            count
+        };
+        self.dirty.store(true, Ordering::Relaxed);
+        self.flush().await;
+        count
+    }
+
+    /// Write the file if entries changed since the last write.
+    pub async fn flush(&self) {
+        let _writing = self.writing.lock().await;
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let json = match serde_json::to_string(&*self.entries.lock().unwrap()) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize TMDB cache: {}", e);
+                self.dirty.store(true, Ordering::Relaxed);
+                return;
+            }
+        };
+        let path = self.path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let tmp = path.with_extension("json.tmp");
+            std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &path))
+        })
+        .await;
+        let error = match result {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        error!(
+            "Failed to write TMDB cache {}: {}",
+            self.path.display(),
+            error
+        );
+        // Retried on the next flush
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_persist_and_purge() {
+        let path =
+            std::env::temp_dir().join(format!("ygege_tmdb_cache_{}.json", std::process::id()));
+        let cache = TitleCache::load(path.clone(), 60);
+        cache.insert(
+            "imdb",
+            "TT0111161",
+            "en-US",
+            vec!["The Shawshank Redemption 1994".into()],
+        );
+        // Nothing is written before the flush
+        assert_eq!(TitleCache::load(path.clone(), 60).len(), 0);
+        cache.flush().await;
+
+        let reloaded = TitleCache::load(path.clone(), 60);
+        assert_eq!(
+            reloaded.get("imdb", "tt0111161", "en-US"),
+            Some(vec!["The Shawshank Redemption 1994".to_string()])
+        );
+        assert_eq!(reloaded.get("imdb", "tt0111161", "fr-FR"), None);
+
+        assert_eq!(reloaded.purge().await, 1);
+        assert_eq!(TitleCache::load(path.clone(), 60).len(), 0);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_flush_retries_after_failure() {
+        let dir = std::env::temp_dir().join(format!("ygege_tmdb_retry_{}", std::process::id()));
+        let path = dir.join("tmdb_cache.json");
+        let cache = TitleCache::load(path.clone(), 60);
+        cache.insert("imdb", "tt0111161", "en-US", vec!["Shawshank".into()]);
+
+        // The directory is missing, the write fails and the entries stay dirty
+        cache.flush().await;
+        assert!(!path.exists());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        cache.flush().await;
+        assert_eq!(TitleCache::load(path, 60).len(), 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,118 @@
+use crate::bencode::Bencode;
+use crate::parser::TorrentDetails;
+use crate::trackers::get_trackers;
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// SHA-1 of the bencoded `info` dictionary, lowercase hex.
+pub fn info_hash(info: &Bencode) -> String {
+    hex::encode(Sha1::digest(info.encode()))
+}
+
+/// Fetch the `info` dictionary of a torrent from the configured caches.
+/// `{infohash}` / `{INFOHASH}` in a URL template are replaced by the lowercase / uppercase hash.
+/// A cached file is only accepted if its `info` dictionary hashes to the requested infohash.
+pub async fn resolve_info(infohash: &str, cache_urls: &[String]) -> Option<Bencode> {
+    if cache_urls.is_empty() {
+        return None;
+    }
+
+    let infohash = infohash.to_lowercase();
+
+    for template in cache_urls {
+        let url = template
+            .replace("{infohash}", &infohash)
+            .replace("{INFOHASH}", &infohash.to_uppercase());
+        match fetch_info(http_client(), &url, &infohash).await {
+            Ok(info) => {
+                debug!("Resolved metadata of {} from {}", infohash, url);
+                return Some(info);
+            }
+            Err(e) => debug!("No metadata for {} from {}: {}", infohash, url, e),
+        }
+    }
+    None
+}
+
+async fn fetch_info(
+    client: &Client,
+    url: &str,
+    infohash: &str,
+) -> Result<Bencode, Box<dyn std::error::Error + Send + Sync>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    let body = response.bytes().await?;
+    let torrent = Bencode::decode(&body)?;
+    let info = torrent
+        .get("info")
+        .filter(|i| matches!(i, Bencode::Dict(_)))
+        .ok_or("No info dictionary")?;
+
+    let hash = info_hash(info);
+    if hash != infohash {
+        return Err(format!("Info hash mismatch (got {})", hash).into());
+    }
+    Ok(info.clone())
+}
+
+/// Build a `.torrent` around a resolved `info` dictionary, announcing to the configured trackers.
+pub fn build_torrent(info: Bencode, details: &TorrentDetails) -> Vec<u8> {
+    let trackers = get_trackers().resolve(&details.trackers);
+
+    let mut torrent = Bencode::dict();
+    if let Some(first) = trackers.first() {
+        torrent.insert("announce", Bencode::str(first));
+    }
+    torrent.insert(
+        "announce-list",
+        Bencode::List(
+            trackers
+                .iter()
+                .map(|t| Bencode::List(vec![Bencode::str(t)]))
+                .collect(),
+        ),
+    );
+    torrent.insert("comment", Bencode::str(&details.torrent.link));
+    torrent.insert(
+        "created by",
+        Bencode::str(&format!("Ygégé {}", crate::VERSION)),
+    );
+    torrent.insert(
+        "creation date",
+        Bencode::Int(details.published_at.unwrap_or(details.created_at) as i64),
+    );
+    torrent.insert("encoding", Bencode::str("UTF-8"));
+    torrent.insert("info", info);
+    torrent.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_hash_of_fixture() {
+        let data = std::fs::read("tests/test.torrent").unwrap();
+        let torrent = Bencode::decode(&data).unwrap();
+        let info = torrent.get("info").unwrap();
+        assert_eq!(info_hash(info), "d984f67af9917b214cd8b6048ab5624c7df6a07a");
+
+        // The hash is computed on the original bytes of the info dictionary
+        let encoded = info.encode();
+        assert!(data.windows(encoded.len()).any(|w| w == encoded));
+    }
+}
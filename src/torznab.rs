@@ -0,0 +1,284 @@
+use crate::categories::{Category, TORZNAB_CUSTOM_OFFSET, cat_id_to_newznab, newznab_name};
+use crate::feed::Enclosure;
+use crate::parser::Torrent;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, UNIX_EPOCH};
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+/// Newznab error codes used by the Torznab API.
+pub const ERR_BAD_CREDENTIALS: u16 = 100;
+pub const ERR_NO_PRIVILEGES: u16 = 102;
+pub const ERR_MISSING_PARAM: u16 = 200;
+pub const ERR_INCORRECT_PARAM: u16 = 201;
+pub const ERR_NO_FUNCTION: u16 = 202;
+pub const ERR_UNKNOWN: u16 = 900;
+
+/// Maximum number of results returned for a single Torznab query.
+pub const MAX_RESULTS: usize = 100;
+
+/// Build the `t=caps` document from the site category tree.
+/// Standard Newznab categories come first, followed by site categories offset by
+/// `TORZNAB_CUSTOM_OFFSET` so indexer managers can pick either.
+pub fn caps_xml(version: &str, categories: &[Category]) -> String {
+    let mut newznab: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for cat in categories
+        .iter()
+        .flat_map(|c| std::iter::once(c).chain(c.sub_categories.iter()))
+    {
+        let mapped = cat_id_to_newznab(cat.id);
+        let parent = mapped / 1000 * 1000;
+        let subs = newznab.entry(parent).or_default();
+        if mapped != parent {
+            subs.insert(mapped);
+        }
+    }
+
+    let mut xml = String::from(XML_HEADER);
+    xml.push_str("<caps>");
+    xml.push_str(&format!(
+        r#"<server version="{}" title="Ygégé" />"#,
+        xml_escape(version)
+    ));
+    xml.push_str(&format!(
+        r#"<limits max="{0}" default="{0}" />"#,
+        MAX_RESULTS
+    ));
+    xml.push_str("<searching>");
+    xml.push_str(r#"<search available="yes" supportedParams="q" />"#);
+    xml.push_str(
+        r#"<tv-search available="yes" supportedParams="q,season,ep,imdbid,tmdbid,tvdbid" />"#,
+    );
+    xml.push_str(r#"<movie-search available="yes" supportedParams="q,imdbid,tmdbid" />"#);
+    xml.push_str(r#"<music-search available="no" supportedParams="q" />"#);
+    xml.push_str(r#"<audio-search available="no" supportedParams="q" />"#);
+    xml.push_str(r#"<book-search available="no" supportedParams="q" />"#);
+    xml.push_str("</searching>");
+
+    xml.push_str("<categories>");
+    for (parent, subs) in &newznab {
+        xml.push_str(&format!(
+            r#"<category id="{}" name="{}">"#,
+            parent,
+            newznab_name(*parent)
+        ));
+        for sub in subs {
+            xml.push_str(&format!(
+                r#"<subcat id="{}" name="{}" />"#,
+                sub,
+                newznab_name(*sub)
+            ));
+        }
+        xml.push_str("</category>");
+    }
+    for cat in categories {
+        xml.push_str(&format!(
+            r#"<category id="{}" name="{}">"#,
+            cat.id + TORZNAB_CUSTOM_OFFSET,
+            xml_escape(&cat.name)
+        ));
+        for sub in &cat.sub_categories {
+            xml.push_str(&format!(
+                r#"<subcat id="{}" name="{}" />"#,
+                sub.id + TORZNAB_CUSTOM_OFFSET,
+                xml_escape(&format!("{} {}", cat.name, sub.name))
+            ));
+        }
+        xml.push_str("</category>");
+    }
+    xml.push_str("</categories>");
+    xml.push_str("</caps>");
+    xml
+}
+
+/// Build an RSS 2.0 document with Torznab attributes for a list of results.
+/// `base_url` is the externally visible address of this server, used for download links
+/// along with the `apikey` of the request. `.torrent` enclosures need `torrent_cache_urls`,
+/// without it the enclosures are the magnets.
+pub fn results_xml(
+    torrents: &[Torrent],
+    base_url: &str,
+    apikey: Option<&str>,
+    enclosure: Enclosure,
+) -> String {
+    let mut xml = String::from(XML_HEADER);
+    xml.push_str(
+        r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:torznab="http://torznab.com/schemas/2015/feed">"#,
+    );
+    xml.push_str("<channel>");
+    xml.push_str(&format!(
+        r#"<atom:link href="{}/api" rel="self" type="application/rss+xml" />"#,
+        xml_escape(base_url)
+    ));
+    xml.push_str("<title>Ygégé</title>");
+    xml.push_str("<description>ygg.gratis via Nostr</description>");
+    xml.push_str(&format!("<link>{}/</link>", xml_escape(base_url)));
+    xml.push_str("<language>fr-FR</language>");
+
+    for torrent in torrents {
+        xml.push_str(&item_xml(torrent, base_url, apikey, enclosure));
+    }
+
+    xml.push_str("</channel>");
+    xml.push_str("</rss>");
+    xml
+}
+
+fn item_xml(
+    torrent: &Torrent,
+    base_url: &str,
+    apikey: Option<&str>,
+    enclosure: Enclosure,
+) -> String {
+    let mut download = format!("{}/torrent/{}", base_url, torrent.id);
+    let mut torrent_file = format!("{}?format=torrent", download);
+    if let Some(apikey) = apikey {
+        let apikey = urlencoding::encode(apikey);
+        download.push_str(&format!("?apikey={}", apikey));
+        torrent_file.push_str(&format!("&apikey={}", apikey));
+    }
+    let enclosure_url = match enclosure {
+        Enclosure::Torrent => &torrent_file,
+        Enclosure::Magnet => &torrent.magnet,
+    };
+    let newznab_cat = cat_id_to_newznab(torrent.category_id);
+    let pub_date = rfc2822_date(torrent.age_stamp as u64);
+
+    let mut xml = String::from("<item>");
+    xml.push_str(&format!("<title>{}</title>", xml_escape(&torrent.name)));
+    xml.push_str(&format!(
+        r#"<guid isPermaLink="false">{}</guid>"#,
+        xml_escape(&torrent.id)
+    ));
+    xml.push_str(&format!("<link>{}</link>", xml_escape(&download)));
+    xml.push_str(&format!(
+        "<comments>{}</comments>",
+        xml_escape(&torrent.link)
+    ));
+    xml.push_str(&format!("<pubDate>{}</pubDate>", pub_date));
+    xml.push_str(&format!("<size>{}</size>", torrent.size));
+    xml.push_str(&format!("<category>{}</category>", newznab_cat));
+    xml.push_str(&format!(
+        r#"<enclosure url="{}" length="{}" type="application/x-bittorrent" />"#,
+        xml_escape(enclosure_url),
+        torrent.size
+    ));
+
+    let attrs: [(&str, String); 11] = [
+        ("category", newznab_cat.to_string()),
+        (
+            "category",
+            (torrent.category_id + TORZNAB_CUSTOM_OFFSET).to_string(),
+        ),
+        ("size", torrent.size.to_string()),
+        ("files", torrent.file_count.to_string()),
+        ("seeders", torrent.seed.to_string()),
+        ("peers", (torrent.seed + torrent.leech).to_string()),
+        ("grabs", torrent.completed.to_string()),
+        ("infohash", torrent.infohash.clone()),
+        ("magneturl", torrent.magnet.clone()),
+        ("downloadvolumefactor", "0".to_string()),
+        ("uploadvolumefactor", "1".to_string()),
+    ];
+    // Let Radarr/Sonarr match on the IDs the event is tagged with
+    let external_ids = torrent.external_ids.iter().filter_map(|id| {
+        match (id.source.as_str(), id.id.split_once(':')) {
+            ("imdb", _) => Some(("imdbid", id.id.clone())),
+            ("tmdb", Some((_, tmdb_id))) => Some(("tmdbid", tmdb_id.to_string())),
+            _ => None,
+        }
+    });
+    for (name, value) in attrs.into_iter().chain(external_ids) {
+        xml.push_str(&format!(
+            r#"<torznab:attr name="{}" value="{}" />"#,
+            name,
+            xml_escape(&value)
+        ));
+    }
+
+    xml.push_str("</item>");
+    xml
+}
+
+/// Build a Newznab error document.
+pub fn error_xml(code: u16, description: &str) -> String {
+    format!(
+        r#"{}<error code="{}" description="{}" />"#,
+        XML_HEADER,
+        code,
+        xml_escape(description)
+    )
+}
+
+/// Format a unix timestamp as an RFC 2822 date (e.g. `Sun, 06 Nov 1994 08:49:37 GMT`).
+pub fn rfc2822_date(timestamp: u64) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(timestamp))
+}
+
+pub fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categories::init_categories;
+
+    #[test]
+    fn test_caps_lists_newznab_and_custom_categories() {
+        let caps = caps_xml("1.0", &init_categories());
+        assert!(caps.contains(r#"<category id="5000" name="TV">"#));
+        assert!(caps.contains(r#"<subcat id="5070" name="TV/Anime" />"#));
+        assert!(caps.contains(r#"<category id="102145" name="Film/Vidéo">"#));
+    }
+
+    #[test]
+    fn test_item_external_id_attrs() {
+        let torrent = Torrent {
+            id: "abc".to_string(),
+            category_id: 2183,
+            name: "Vaiana 2 2024".to_string(),
+            external_ids: vec![
+                crate::parser::ExternalId::imdb("13622776"),
+                crate::parser::ExternalId::tmdb("1241982", false),
+            ],
+            magnet: "magnet:?xt=urn:btih:abc&dn=Vaiana".to_string(),
+            ..Default::default()
+        };
+        let xml = item_xml(
+            &torrent,
+            "http://localhost:8715",
+            Some("k&y"),
+            Enclosure::Torrent,
+        );
+        assert!(xml.contains("<link>http://localhost:8715/torrent/"));
+        assert!(xml.contains("?apikey=k%26y</link>"));
+        assert!(xml.contains(
+            r#"<enclosure url="http://localhost:8715/torrent/abc?format=torrent&amp;apikey=k%26y""#
+        ));
+        assert!(xml.contains(r#"<torznab:attr name="imdbid" value="tt13622776" />"#));
+        assert!(xml.contains(r#"<torznab:attr name="tmdbid" value="1241982" />"#));
+
+        let xml = item_xml(&torrent, "http://localhost:8715", None, Enclosure::Magnet);
+        assert!(xml.contains(r#"<enclosure url="magnet:?xt=urn:btih:abc&amp;dn=Vaiana""#));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(
+            xml_escape(r#"Tom & Jerry <"1080p">"#),
+            "Tom &amp; Jerry &lt;&quot;1080p&quot;&gt;"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use crate::config::{Config, TrackerMode};
+use std::collections::HashSet;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// Delay between two reachability probes of the tracker list.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Time a tracker has to answer a probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// BEP-15 magic constant sent in UDP tracker connect requests.
+const UDP_PROTOCOL_ID: u64 = 0x41727101980;
+
+static TRACKERS: OnceLock<Trackers> = OnceLock::new();
+
+/// Tracker list used by ygg.gratis for its magnet links.
+pub const BUILTIN_TRACKERS: &[&str] = &[
+    "https://tracker.yggleak.top/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://open.demonii.com:1337/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://exodus.desync.com:6969/announce",
+    "https://torrent.tracker.durukanbal.com:443/announce",
+    "udp://tracker1.myporn.club:9337/announce",
+    "udp://tracker.torrent.eu.org:451/announce",
+    "udp://tracker.theoks.net:6969/announce",
+    "udp://tracker.srv00.com:6969/announce",
+    "udp://tracker.filemail.com:6969/announce",
+    "udp://tracker.dler.org:6969/announce",
+    "udp://tracker.corpscorp.online:80/announce",
+    "udp://tracker.alaskantf.com:6969/announce",
+    "udp://tracker-udp.gbitt.info:80/announce",
+    "udp://t.overflow.biz:6969/announce",
+    "udp://open.dstud.io:6969/announce",
+    "udp://leet-tracker.moe:1337/announce",
+    "udp://explodie.org:6969/announce",
+    "udp://bittorrent-tracker.e-n-c-r-y-p-t.net:1337/announce",
+    "udp://6ahddutb1ucc3cp.ru:6969/announce",
+    "udp://94.23.207.177:6969/announce",
+    "udp://37.59.48.81:6969/announce",
+    "udp://54.36.179.216:6969/announce",
+    "udp://193.42.111.57:9337/announce",
+    "udp://43.250.54.137:6969/announce",
+    "udp://91.216.110.53:451/announce",
+    "udp://45.134.88.121:6969/announce",
+    "udp://135.125.236.64:6969/announce",
+    "udp://5.255.124.190:6969/announce",
+    "udp://93.158.213.92:1337/announce",
+    "udp://107.189.4.235:1337/announce",
+    "udp://tracker.qu.ax:6969/announce",
+    "udp://107.189.7.165:6969/announce",
+    "udp://103.251.166.126:6969/announce",
+    "udp://185.243.218.213:80/announce",
+    "http://tracker.zhuqiy.com:80/announce",
+    "udp://81.230.84.201:6969/announce",
+    "udp://212.42.38.197:6969/announce",
+    "http://193.31.26.113:6969/announce",
+    "udp://176.99.7.59:6969/announce",
+    "http://tr.nyacat.pw:80/announce",
+];
+
+/// The tracker list added to magnet links and `.torrent` files.
+pub struct Trackers {
+    mode: TrackerMode,
+    /// Configured list, or the built-in one when nothing is configured
+    list: Vec<String>,
+    /// Trackers that answered the last probe, `None` until pruning ran once
+    reachable: RwLock<Option<HashSet<String>>>,
+}
+
+impl Trackers {
+    /// Trackers to announce to for an event carrying `event_trackers`.
+    pub fn resolve(&self, event_trackers: &[String]) -> Vec<String> {
+        let configured = self.configured();
+        let candidates: Vec<&String> = match self.mode {
+            TrackerMode::Builtin | TrackerMode::Custom => configured.iter().collect(),
+            // Events without trackers would give magnets with DHT only
+            TrackerMode::Event if event_trackers.is_empty() => configured.iter().collect(),
+            TrackerMode::Event => event_trackers.iter().collect(),
+            TrackerMode::Merge => event_trackers.iter().chain(configured.iter()).collect(),
+        };
+
+        let mut trackers: Vec<String> = Vec::new();
+        for tracker in candidates {
+            if !trackers.contains(tracker) {
+                trackers.push(tracker.clone());
+            }
+        }
+        trackers
+    }
+
+    /// The configured list without the trackers that failed the last probe.
+    /// The full list is kept if every tracker failed, the network is more likely at fault.
+    fn configured(&self) -> Vec<String> {
+        let reachable = self.reachable.read().unwrap();
+        match reachable.as_ref() {
+            Some(reachable) if !reachable.is_empty() => self
+                .list
+                .iter()
+                .filter(|t| reachable.contains(*t))
+                .cloned()
+                .collect(),
+            _ => self.list.clone(),
+        }
+    }
+}
+
+/// The tracker list built by `init_trackers`.
+pub fn get_trackers() -> &'static Trackers {
+    TRACKERS.get().expect("Tracker list not initialized")
+}
+
+/// Test fixture: the built-in list in the default mode.
+#[cfg(test)]
+pub fn init_test_trackers() {
+    TRACKERS.get_or_init(|| Trackers {
+        mode: TrackerMode::default(),
+        list: builtin(),
+        reachable: RwLock::new(None),
+    });
+}
+
+fn builtin() -> Vec<String> {
+    BUILTIN_TRACKERS.iter().map(|t| t.to_string()).collect()
+}
+
+/// Build the tracker list from the configuration and start pruning it when enabled.
+pub async fn init_trackers(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut list: Vec<String> = Vec::new();
+    if matches!(
+        config.tracker_mode,
+        TrackerMode::Custom | TrackerMode::Merge
+    ) {
+        list.extend(config.trackers.iter().cloned());
+        if let Some(source) = &config.trackers_source {
+            match load_source(source, config.use_tor).await {
+                Ok(loaded) => {
+                    info!("Loaded {} trackers from {}", loaded.len(), source);
+                    list.extend(loaded);
+                }
+                Err(e) => warn!("Failed to load trackers from {}: {}", source, e),
+            }
+        }
+    }
+    // Merge adds the built-in list to the custom one; an empty custom list falls back to it
+    if config.tracker_mode != TrackerMode::Custom || list.is_empty() {
+        if config.tracker_mode == TrackerMode::Custom {
+            warn!("Custom tracker list is empty, using the built-in list");
+        }
+        list.extend(builtin());
+    }
+    let mut seen = HashSet::new();
+    list.retain(|t| seen.insert(t.clone()));
+
+    info!(
+        "Tracker mode: {:?} ({} trackers)",
+        config.tracker_mode,
+        list.len()
+    );
+    TRACKERS
+        .set(Trackers {
+            mode: config.tracker_mode,
+            list,
+            reachable: RwLock::new(None),
+        })
+        .map_err(|_| "Tracker list already initialized")?;
+
+    if config.prune_trackers && config.use_tor {
+        // Probes can't go through the SOCKS proxy and would reveal the real IP to every tracker
+        warn!("Tracker pruning is disabled with Tor, probes would not go through the proxy");
+    } else if config.prune_trackers {
+        tokio::spawn(prune_loop());
+    }
+    Ok(())
+}
+
+/// Read a tracker list, one URL per line, from a file or an HTTP(S) URL.
+/// Blank lines and lines starting with `#` are ignored.
+/// URLs are refused with Tor, the request would not go through the proxy.
+async fn load_source(
+    source: &str,
+    use_tor: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let remote = source.starts_with("http://") || source.starts_with("https://");
+    if remote && use_tor {
+        return Err("remote tracker lists are not fetched through Tor, use a local file".into());
+    }
+    let content = if remote {
+        reqwest::get(source)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?
+    } else {
+        std::fs::read_to_string(source)?
+    };
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+async fn prune_loop() {
+    let trackers = get_trackers();
+    loop {
+        let probes = trackers.list.iter().map(|t| async move {
+            let reachable = probe(t).await;
+            if !reachable {
+                debug!("Tracker {} is unreachable", t);
+            }
+            (t.clone(), reachable)
+        });
+        let reachable: HashSet<String> = futures::future::join_all(probes)
+            .await
+            .into_iter()
+            .filter(|(_, ok)| *ok)
+            .map(|(t, _)| t)
+            .collect();
+
+        info!(
+            "Tracker probe: {}/{} reachable",
+            reachable.len(),
+            trackers.list.len()
+        );
+        *trackers.reachable.write().unwrap() = Some(reachable);
+        tokio::time::sleep(PRUNE_INTERVAL).await;
+    }
+}
+
+/// Check that an announce URL answers: a BEP-15 connect for UDP trackers, any HTTP response
+/// for HTTP ones. Other schemes (e.g. `wss://`) are assumed reachable.
+async fn probe(tracker: &str) -> bool {
+    let Ok(url) = url::Url::parse(tracker) else {
+        return false;
+    };
+    match url.scheme() {
+        "udp" => {
+            let (Some(host), Some(port)) = (url.host_str(), url.port()) else {
+                return false;
+            };
+            tokio::time::timeout(PROBE_TIMEOUT, probe_udp(host, port))
+                .await
+                .is_ok_and(|r| r.unwrap_or(false))
+        }
+        "http" | "https" => {
+            let Ok(client) = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build() else {
+                return false;
+            };
+            // A bare announce gets an error body, any response means the tracker is up
+            client.get(tracker).send().await.is_ok()
+        }
+        _ => true,
+    }
+}
+
+async fn probe_udp(host: &str, port: u16) -> std::io::Result<bool> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((host, port)).await?;
+
+    let transaction_id: u32 = rand::random();
+    let mut request = Vec::with_capacity(16);
+    request.extend_from_slice(&UDP_PROTOCOL_ID.to_be_bytes());
+    request.extend_from_slice(&0u32.to_be_bytes()); // action: connect
+    request.extend_from_slice(&transaction_id.to_be_bytes());
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 16];
+    let len = socket.recv(&mut response).await?;
+    Ok(len >= 16
+        && response[0..4] == 0u32.to_be_bytes()
+        && response[4..8] == transaction_id.to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trackers(mode: TrackerMode) -> Trackers {
+        Trackers {
+            mode,
+            list: vec!["udp://a:1/announce".into(), "udp://b:1/announce".into()],
+            reachable: RwLock::new(None),
+        }
+    }
+
+    #[test]
+    fn test_resolve_modes() {
+        let event = vec![
+            "udp://e:1/announce".to_string(),
+            "udp://a:1/announce".into(),
+        ];
+
+        assert_eq!(trackers(TrackerMode::Custom).resolve(&event).len(), 2);
+        assert_eq!(trackers(TrackerMode::Event).resolve(&event), event);
+        assert_eq!(trackers(TrackerMode::Event).resolve(&[]).len(), 2);
+        assert_eq!(
+            trackers(TrackerMode::Merge).resolve(&event),
+            vec![
+                "udp://e:1/announce",
+                "udp://a:1/announce",
+                "udp://b:1/announce"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pruned_list_keeps_reachable_trackers() {
+        let list = trackers(TrackerMode::Builtin);
+        *list.reachable.write().unwrap() = Some(HashSet::from(["udp://b:1/announce".into()]));
+        assert_eq!(list.resolve(&[]), vec!["udp://b:1/announce"]);
+
+        *list.reachable.write().unwrap() = Some(HashSet::new());
+        assert_eq!(list.resolve(&[]).len(), 2);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::ban_words::BanWords;
+use crate::config::data_path;
+use crate::feed::summary;
+use crate::filters::SearchFilter;
+use crate::parser::Torrent;
+use crate::stream::{StreamFilter, subscribe_live};
+use qstring::QString;
+use regex::{Regex, RegexBuilder};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+
+const RULES_FILE: &str = "watch_rules.json";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+static WATCH_STORE: OnceLock<WatchStore> = OnceLock::new();
+static WEBHOOK_CLIENT: OnceLock<Client> = OnceLock::new();
+
+pub fn get_watch_store() -> Option<&'static WatchStore> {
+    WATCH_STORE.get()
+}
+
+/// Load the rules next to `config.json` and notify their webhooks of every new torrent
+/// of the live stream.
+pub fn init_watch() -> Result<(), String> {
+    let path = data_path(RULES_FILE);
+    let store = WatchStore::load(path.clone())
+        .map_err(|e| format!("Watch rules {}: {}", path.display(), e))?;
+    info!(
+        "Watch rules loaded: {} from {}",
+        store.len(),
+        store.path.display()
+    );
+    if WATCH_STORE.set(store).is_err() {
+        warn!("Watch rules already initialized");
+        return Ok(());
+    }
+    let Some(mut live) = subscribe_live() else {
+        warn!("Live stream not initialized, watch rules will not be evaluated");
+        return Ok(());
+    };
+    tokio::spawn(async move {
+        let store = get_watch_store().unwrap();
+        loop {
+            match live.recv().await {
+                Ok(torrent) => {
+                    for rule in store.matching(&torrent) {
+                        info!("Watch rule {} matched {}", rule.name, torrent.name);
+                        for webhook in rule.webhooks.clone() {
+                            let (rule, torrent) = (rule.clone(), torrent.clone());
+                            tokio::spawn(async move { webhook.send(&rule, &torrent).await });
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Watch rules fell behind, {} torrents skipped", missed)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// A saved search: new torrents matching every criterion are posted to the webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchRule {
+    /// Assigned on creation
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// Words that must all appear in the name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Case-insensitive regex the name must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Site categories, a parent category includes its sub-categories
+    #[serde(default)]
+    pub categories: Vec<usize>,
+    #[serde(default)]
+    pub ban_words: Vec<String>,
+    /// `/search` filter parameters, e.g. `{"resolution": "1080p", "min_seed": 5}`
+    #[serde(default)]
+    pub filters: BTreeMap<String, Value>,
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+impl WatchRule {
+    /// Check the rule and compile its criteria.
+    fn matcher(&self) -> Result<Matcher, String> {
+        if self.name.trim().is_empty() {
+            return Err("A watch rule needs a name".to_string());
+        }
+        if self.webhooks.is_empty() {
+            return Err("A watch rule needs at least one webhook".to_string());
+        }
+        for webhook in &self.webhooks {
+            match url::Url::parse(&webhook.url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => return Err(format!("Invalid webhook URL {}", webhook.url)),
+            }
+        }
+        let regex = match &self.regex {
+            Some(pattern) => Some(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("Invalid regex {}: {}", pattern, e))?,
+            ),
+            None => None,
+        };
+        let ban_words = BanWords::parse(&self.ban_words)?;
+        // A misspelled filter would silently match everything
+        if let Some(key) = self
+            .filters
+            .keys()
+            .find(|key| !SearchFilter::PARAMS.contains(&key.as_str()))
+        {
+            return Err(format!(
+                "Unknown filter {}, expected one of {}",
+                key,
+                SearchFilter::PARAMS.join(", ")
+            ));
+        }
+        let params: Vec<(String, String)> = self
+            .filters
+            .iter()
+            .map(|(key, value)| (key.clone(), param_value(value)))
+            .collect();
+        Ok(Matcher {
+            filter: StreamFilter::new(
+                self.categories.clone(),
+                self.query.as_deref().unwrap_or(""),
+                (!ban_words.is_empty()).then_some(ban_words),
+                SearchFilter::from_query(&QString::new(params)),
+            ),
+            regex,
+        })
+    }
+}
+
+/// `"1080p"`, `5` or `["MULTi", "VFF"]` as a query string value.
+fn param_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(param_value).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+struct Matcher {
+    filter: StreamFilter,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    fn matches(&self, torrent: &Torrent) -> bool {
+        self.filter.matches(torrent)
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|r| r.is_match(&torrent.name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// The rule and the `/search` JSON of the torrent
+    #[default]
+    Generic,
+    Discord,
+    /// `url` includes the application `?token=`
+    Gotify,
+    /// `url` is the topic URL, e.g. `https://ntfy.sh/my-topic`
+    Ntfy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    #[serde(default)]
+    pub kind: WebhookKind,
+    pub url: String,
+}
+
+impl Webhook {
+    /// Target URL and JSON body of the notification.
+    pub fn request(&self, rule: &WatchRule, torrent: &Torrent) -> (String, Value) {
+        let title = format!("Ygégé: {}", rule.name);
+        let message = format!("{}\n{}", torrent.name, summary(torrent));
+        match self.kind {
+            WebhookKind::Generic => (
+                self.url.clone(),
+                json!({
+                    "rule": { "id": rule.id, "name": rule.name },
+                    "torrent": torrent.to_json(),
+                }),
+            ),
+            WebhookKind::Discord => (
+                self.url.clone(),
+                json!({
+                    "username": "Ygégé",
+                    "embeds": [{
+                        "title": torrent.name.chars().take(256).collect::<String>(),
+                        "url": torrent.link,
+                        "description": summary(torrent),
+                        "footer": { "text": rule.name },
+                    }],
+                }),
+            ),
+            WebhookKind::Gotify => (
+                self.url.clone(),
+                json!({
+                    "title": title,
+                    "message": message,
+                    "priority": 5,
+                    "extras": { "client::notification": { "click": { "url": torrent.link } } },
+                }),
+            ),
+            // JSON messages are published to the server root with the topic in the body
+            WebhookKind::Ntfy => {
+                let (server, topic) = self
+                    .url
+                    .trim_end_matches('/')
+                    .rsplit_once('/')
+                    .unwrap_or((&self.url, ""));
+                (
+                    server.to_string(),
+                    json!({
+                        "topic": topic,
+                        "title": title,
+                        "message": message,
+                        "click": torrent.link,
+                    }),
+                )
+            }
+        }
+    }
+
+    pub async fn send(&self, rule: &WatchRule, torrent: &Torrent) {
+        let (url, body) = self.request(rule, torrent);
+        let client = WEBHOOK_CLIENT.get_or_init(|| {
+            Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .unwrap_or_default()
+        });
+        let result = client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            warn!(
+                "Webhook {:?} of watch rule {} failed: {}",
+                self.kind, rule.name, e
+            );
+        }
+    }
+}
+
+/// Watch rules persisted as a single JSON file.
+pub struct WatchStore {
+    path: PathBuf,
+    rules: Mutex<Vec<(WatchRule, Arc<Matcher>)>>,
+    /// Held while the file is written, so saves land in order
+    writing: tokio::sync::Mutex<()>,
+}
+
+impl WatchStore {
+    /// Load the rules. A file that can't be parsed is moved aside rather than overwritten
+    /// by the next save, failing when it can't be.
+    fn load(path: PathBuf) -> Result<Self, String> {
+        let rules: Vec<WatchRule> = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    let backup = path.with_extension("json.corrupted");
+                    std::fs::rename(&path, &backup).map_err(|rename_error| {
+                        format!(
+                            "corrupted ({}) and could not be moved aside: {}",
+                            e, rename_error
+                        )
+                    })?;
+                    error!(
+                        "Corrupted watch rules {} ({}), moved to {}",
+                        path.display(),
+                        e,
+                        backup.display()
+                    );
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        let rules = rules
+            .into_iter()
+            .filter_map(|rule| match rule.matcher() {
+                Ok(matcher) => Some((rule, Arc::new(matcher))),
+                Err(e) => {
+                    warn!("Skipping watch rule {}: {}", rule.name, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(WatchStore {
+            path,
+            rules: Mutex::new(rules),
+            writing: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.lock().unwrap().len()
+    }
+
+    pub fn list(&self) -> Vec<WatchRule> {
+        let rules = self.rules.lock().unwrap();
+        rules.iter().map(|(rule, _)| rule.clone()).collect()
+    }
+
+    /// Validate and save a new rule. Returns it with its `id`.
+    pub async fn add(&self, mut rule: WatchRule) -> Result<WatchRule, String> {
+        let matcher = rule.matcher()?;
+        rule.id = uuid::Uuid::new_v4().to_string();
+        rule.created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let writing = self.writing.lock().await;
+        let json = {
+            let mut rules = self.rules.lock().unwrap();
+            rules.push((rule.clone(), Arc::new(matcher)));
+            to_json(&rules)
+        };
+        self.save(json).await;
+        drop(writing);
+        Ok(rule)
+    }
+
+    /// Returns whether the rule existed.
+    pub async fn remove(&self, id: &str) -> bool {
+        let writing = self.writing.lock().await;
+        let json = {
+            let mut rules = self.rules.lock().unwrap();
+            let count = rules.len();
+            rules.retain(|(rule, _)| rule.id != id);
+            (rules.len() != count).then(|| to_json(&rules))
+        };
+        let removed = json.is_some();
+        if let Some(json) = json {
+            self.save(json).await;
+        }
+        drop(writing);
+        removed
+    }
+
+    pub fn matching(&self, torrent: &Torrent) -> Vec<WatchRule> {
+        let rules = self.rules.lock().unwrap();
+        rules
+            .iter()
+            .filter(|(_, matcher)| matcher.matches(torrent))
+            .map(|(rule, _)| rule.clone())
+            .collect()
+    }
+
+    /// Write the rules off the async executor, through a temporary file so a crash never
+    /// leaves a truncated file behind.
+    async fn save(&self, json: String) {
+        let path = self.path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let tmp = path.with_extension("json.tmp");
+            std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &path))
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to write watch rules {}: {}", self.path.display(), e),
+            Err(e) => error!("Failed to write watch rules {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn to_json(rules: &[(WatchRule, Arc<Matcher>)]) -> String {
+    let rules: Vec<&WatchRule> = rules.iter().map(|(rule, _)| rule).collect();
+    serde_json::to_string_pretty(&rules).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(name: &str, seed: usize) -> Torrent {
+        Torrent {
+            id: "e1".to_string(),
+            category_id: 2184,
+            seed,
+            link: "https://ygg.gratis/engine/torrent?id=e1".to_string(),
+            ..Torrent::named(name)
+        }
+    }
+
+    fn rule() -> WatchRule {
+        serde_json::from_value(json!({
+            "name": "Severance",
+            "regex": "^severance\\.s02",
+            "categories": [2184],
+            "filters": { "resolution": "1080p", "min_seed": 5 },
+            "webhooks": [{ "kind": "ntfy", "url": "https://ntfy.sh/series" }],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_store_and_match() {
+        let path = std::env::temp_dir().join(format!("ygege_watch_{}.json", std::process::id()));
+        let store = WatchStore::load(path.clone()).unwrap();
+        let added = store.add(rule()).await.unwrap();
+        assert!(!added.id.is_empty());
+
+        let mut invalid = rule();
+        invalid.regex = Some("(".to_string());
+        assert!(store.add(invalid).await.is_err());
+
+        let mut misspelled = rule();
+        misspelled.filters.insert("min_seeds".to_string(), json!(5));
+        assert!(store.add(misspelled).await.is_err());
+
+        let reloaded = WatchStore::load(path.clone()).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(
+            reloaded
+                .matching(&torrent("Severance.S02E03.MULTi.1080p.WEB-DL", 8))
+                .len(),
+            1
+        );
+        assert!(
+            reloaded
+                .matching(&torrent("Severance.S02E03.MULTi.1080p.WEB-DL", 2))
+                .is_empty()
+        );
+        assert!(
+            reloaded
+                .matching(&torrent("Severance.S01E03.MULTi.1080p.WEB-DL", 8))
+                .is_empty()
+        );
+
+        assert!(reloaded.remove(&added.id).await);
+        assert_eq!(WatchStore::load(path.clone()).unwrap().len(), 0);
+
+        // A corrupted file is kept aside instead of being overwritten
+        std::fs::write(&path, "[{").unwrap();
+        assert_eq!(WatchStore::load(path.clone()).unwrap().len(), 0);
+        let backup = path.with_extension("json.corrupted");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "[{");
+        assert!(!path.exists());
+        std::fs::remove_file(backup).ok();
+    }
+
+    #[test]
+    fn test_webhook_requests() {
+        let rule = rule();
+        let torrent = torrent("Severance.S02E03.MULTi.1080p.WEB-DL", 8);
+
+        let (url, body) = rule.webhooks[0].request(&rule, &torrent);
+        assert_eq!(url, "https://ntfy.sh");
+        assert_eq!(body["topic"], "series");
+        assert_eq!(body["click"], torrent.link.as_str());
+
+        let generic = Webhook {
+            kind: WebhookKind::Generic,
+            url: "http://localhost/hook".to_string(),
+        };
+        let (_, body) = generic.request(&rule, &torrent);
+        assert_eq!(body["torrent"]["name"], torrent.name.as_str());
+        assert_eq!(body["rule"]["name"], "Severance");
+    }
+}